@@ -1,16 +1,34 @@
 use clap::{App, Arg};
-use std::str::FromStr;
+use phased_in_codes::common::{BitOrder, Padding, StreamOptions, WordSize};
+use phased_in_codes::pipeline::{DEFAULT_LEVEL, MAX_LEVEL};
+use std::{collections::HashMap, env, ffi::OsString, fs, str::FromStr};
+
+/// The environment variable that points to the config file when `--config` is omitted
+const CONFIG_ENV_VAR: &str = "PHASED_IN_CODES_CONFIG";
 
 pub enum Action {
     Compress,
     Decompress,
+    ListTransforms,
+    Analyze,
 }
 
 pub struct Cli {
-    pub num_symbols: u8,
+    pub num_symbols: Option<u32>,
     pub action: Action,
     pub input_file: String,
     pub output_file: String,
+    pub store_freq: bool,
+    pub stats: bool,
+    pub dry_run: bool,
+    pub json: bool,
+    pub densify: bool,
+    pub alphabet: Option<Vec<u8>>,
+    pub channels: u32,
+    pub payload_checksum: bool,
+    pub options: StreamOptions,
+    pub level: u8,
+    pub padding: Padding,
 }
 
 impl Cli {
@@ -28,7 +46,6 @@ impl Cli {
                     .takes_value(true)
                     .min_values(1)
                     .max_values(1)
-                    .required(true)
             )
             .arg(
                 Arg::with_name("compress_action")
@@ -36,7 +53,7 @@ impl Cli {
                     .long("--compress")
                     .help("Compress input")
                     .takes_value(false)
-                    .required_unless("decompress_action")
+                    .required_unless_one(&["decompress_action", "list_transforms", "analyze"])
             )
             .arg(
                 Arg::with_name("decompress_action")
@@ -44,13 +61,23 @@ impl Cli {
                     .long("--decompress")
                     .help("Decompress input")
             )
+            .arg(
+                Arg::with_name("list_transforms")
+                    .long("--list-transforms")
+                    .help("List the transforms that can be applied to the input and exit")
+            )
+            .arg(
+                Arg::with_name("analyze")
+                    .long("--analyze")
+                    .help("Print a report about the input and the settings that suit it, without compressing it")
+            )
             .arg(
                 Arg::with_name("input_file")
                     .short("-i")
                     .long("--input")
                     .help("Specify the input file to compress or decompress")
                     .takes_value(true)
-                    .required(true)
+                    .required_unless("list_transforms")
                     .min_values(1)
                     .max_values(1)
             )
@@ -60,30 +87,357 @@ impl Cli {
                     .long("--output")
                     .help("Specify the output file to write the compressed/decompressed input")
                     .takes_value(true)
-                    .required(true)
+                    .required_unless_one(&["list_transforms", "analyze"])
                     .min_values(1)
                     .max_values(1)
             )
+            .arg(
+                Arg::with_name("config")
+                    .long("--config")
+                    .value_name("FILE")
+                    .help("Read the options that are omitted from a config file. Defaults to $PHASED_IN_CODES_CONFIG")
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::with_name("store_freq")
+                    .long("--store-freq")
+                    .help("Store the frequency of each symbol in the header of the compressed file")
+                    .takes_value(false)
+            )
+            .arg(
+                Arg::with_name("stats")
+                    .long("--stats")
+                    .help("Print statistics about the input and how well it was compressed")
+                    .takes_value(false)
+            )
+            .arg(
+                Arg::with_name("dry_run")
+                    .long("--dry-run")
+                    .help("Compress or decompress in memory and report the result without writing the output file")
+                    .takes_value(false)
+            )
+            .arg(
+                Arg::with_name("json")
+                    .long("--json")
+                    .help("Print a JSON summary of the run, or of the analysis, instead of the human-readable report")
+                    .takes_value(false)
+            )
+            .arg(
+                Arg::with_name("densify")
+                    .long("--densify")
+                    .help("Pack the bytes the input uses into the smallest alphabet that holds them before compressing")
+                    .takes_value(false)
+            )
+            .arg(
+                Arg::with_name("alphabet")
+                    .long("--alphabet")
+                    .value_name("BYTES")
+                    .help("Specify the comma separated bytes the input is made of. Implies the number of symbols")
+                    .takes_value(true)
+                    .validator(|alphabet| match parse_alphabet(&alphabet) {
+                        Some(_) => Ok(()),
+                        None => Err(String::from("the alphabet must be a comma separated list of bytes")),
+                    })
+            )
+            .arg(
+                Arg::with_name("channels")
+                    .long("--channels")
+                    .value_name("CHANNELS")
+                    .help("Specify the number of channels of channel-interleaved input, to group every channel together")
+                    .takes_value(true)
+                    .default_value("1")
+            )
+            .arg(
+                Arg::with_name("payload_checksum")
+                    .long("--payload-checksum")
+                    .help("Store the checksum of the compressed bytes in the header, so that corrupted files are rejected")
+                    .takes_value(false)
+            )
+            .arg(
+                Arg::with_name("bit_order")
+                    .long("--bit-order")
+                    .value_name("ORDER")
+                    .help("Specify whether the encoded bits are packed starting from the most or least significant bit")
+                    .takes_value(true)
+                    .possible_values(&["msb", "lsb"])
+                    .default_value("msb")
+            )
+            .arg(
+                Arg::with_name("word_size")
+                    .long("--word-size")
+                    .value_name("BITS")
+                    .help("Specify the size of the little endian words the input is split into")
+                    .takes_value(true)
+                    .possible_values(&["8", "16", "32"])
+                    .default_value("8")
+            )
+            .arg(
+                Arg::with_name("offset")
+                    .long("--offset")
+                    .value_name("OFFSET")
+                    .help("Specify the value of the smallest word in your input")
+                    .takes_value(true)
+                    .default_value("0")
+            )
+            .arg(
+                Arg::with_name("level")
+                    .short("-l")
+                    .long("--level")
+                    .value_name("LEVEL")
+                    .help("Specify the compression level, from 0 (stored) to 9 (all the transforms)")
+                    .takes_value(true)
+                    .validator(|level| match u8::from_str(&level) {
+                        Ok(level) if level <= MAX_LEVEL => Ok(()),
+                        _ => Err(format!("the level must be between 0 and {}", MAX_LEVEL)),
+                    })
+            )
+            .arg(
+                Arg::with_name("padding")
+                    .long("--padding")
+                    .value_name("PADDING")
+                    .help("Specify how the unused bits of the last byte are filled: zeros, ones or a byte pattern such as 0xAA")
+                    .takes_value(true)
+                    .default_value("zeros")
+                    .validator(|padding| match parse_padding(&padding) {
+                        Some(_) => Ok(()),
+                        None => Err(String::from("the padding must be zeros, ones or a byte pattern")),
+                    })
+            )
     }
 
-    pub fn from_args() -> Option<Self> {
-        let app = Cli::build_app();
-        let matches = app.get_matches();
+    /// Parses the arguments the program was run with
+    pub fn from_args() -> Result<Self, String> {
+        Cli::from_arg_list(env::args_os())
+    }
+
+    /// Parses `args`, filling in the options that were omitted from the [`Config`] file, if there is one
+    fn from_arg_list<I, T>(args: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let matches = Cli::build_app().get_matches_from(args);
+        let config = match matches.value_of("config").map(String::from).or_else(|| env::var(CONFIG_ENV_VAR).ok()) {
+            Some(path) => Config::read(&path)?,
+            None => Config::default(),
+        };
 
-        let num_symbols = u8::from_str(matches.value_of("num_symbols")?).ok()?;
-        let input_file = matches.value_of("input_file")?.to_owned();
-        let output_file = matches.value_of("output_file")?.to_owned();
-        let action = if matches.is_present("compress_action") {
+        let value_of = |name: &str| -> Option<&str> {
+            if matches.occurrences_of(name) != 0 {
+                matches.value_of(name)
+            } else {
+                config.get(name).or_else(|| matches.value_of(name))
+            }
+        };
+        let invalid = |name: &str| format!("invalid value '{}' for {}", value_of(name).unwrap_or_default(), name);
+
+        let alphabet = match value_of("alphabet") {
+            Some(alphabet) => Some(parse_alphabet(alphabet).ok_or_else(|| invalid("alphabet"))?),
+            None => None,
+        };
+        let num_symbols = match value_of("num_symbols") {
+            Some(num_symbols) => Some(u32::from_str(num_symbols).map_err(|_| invalid("num_symbols"))?),
+            None => alphabet.as_ref().map(|alphabet| alphabet.len() as u32),
+        };
+        let input_file = matches.value_of("input_file").unwrap_or_default().to_owned();
+        let output_file = matches.value_of("output_file").unwrap_or_default().to_owned();
+        let flag = |name: &str| matches.is_present(name) || config.get(name) == Some("true");
+        let store_freq = flag("store_freq");
+        let stats = matches.is_present("stats");
+        let dry_run = matches.is_present("dry_run");
+        let json = matches.is_present("json");
+        let densify = flag("densify");
+        let payload_checksum = flag("payload_checksum");
+        let bit_order = match value_of("bit_order") {
+            Some("lsb") => BitOrder::Lsb0,
+            Some("msb") => BitOrder::Msb0,
+            _ => return Err(invalid("bit_order")),
+        };
+        let word_size = value_of("word_size")
+            .and_then(|bits| u8::from_str(bits).ok())
+            .and_then(WordSize::from_bits)
+            .ok_or_else(|| invalid("word_size"))?;
+        let offset = value_of("offset")
+            .and_then(|offset| u32::from_str(offset).ok())
+            .ok_or_else(|| invalid("offset"))?;
+        let level = match value_of("level") {
+            Some(level) => u8::from_str(level)
+                .ok()
+                .filter(|level| *level <= MAX_LEVEL)
+                .ok_or_else(|| invalid("level"))?,
+            None => DEFAULT_LEVEL,
+        };
+        let channels = value_of("channels")
+            .and_then(|channels| u32::from_str(channels).ok())
+            .filter(|channels| *channels != 0)
+            .ok_or_else(|| invalid("channels"))?;
+        let padding = value_of("padding").and_then(parse_padding).ok_or_else(|| invalid("padding"))?;
+        let action = if matches.is_present("list_transforms") {
+            Action::ListTransforms
+        } else if matches.is_present("analyze") {
+            Action::Analyze
+        } else if matches.is_present("compress_action") {
             Action::Compress
         } else {
             Action::Decompress
         };
 
-        Some(Cli {
+        if let (Action::Compress, None) = (&action, num_symbols) {
+            return Err(String::from("compressing requires the number of symbols, given using -s or the config file"));
+        }
+
+        Ok(Cli {
             num_symbols,
             action,
             input_file,
             output_file,
+            store_freq,
+            stats,
+            dry_run,
+            json,
+            densify,
+            alphabet,
+            channels,
+            payload_checksum,
+            options: StreamOptions {
+                bit_order,
+                word_size,
+                offset,
+            },
+            level,
+            padding,
         })
     }
-}
\ No newline at end of file
+}
+
+/// The options read from a config file, used for the options that are omitted from the command line.
+///
+/// Every line of the file is a `name = value` pair, where the name is one of `num_symbols`, `alphabet`,
+/// `level`, `channels`, `bit_order`, `word_size`, `offset`, `padding`, `store_freq`, `densify` or `payload_checksum`.
+/// The flags are set by a value of `true`, and a flag given on the command line is set regardless of the file.
+/// Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Default)]
+struct Config {
+    values: HashMap<String, String>,
+}
+
+impl Config {
+    /// The options that can be set in a config file
+    const OPTIONS: [&'static str; 11] = [
+        "num_symbols",
+        "alphabet",
+        "level",
+        "channels",
+        "bit_order",
+        "word_size",
+        "offset",
+        "padding",
+        "store_freq",
+        "densify",
+        "payload_checksum",
+    ];
+
+    /// Reads and parses the config file at `path`
+    fn read(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|error| format!("could not read config '{}': {}", path, error))?;
+        Config::parse(&contents).map_err(|message| format!("{} in config '{}'", message, path))
+    }
+
+    /// Parses the `contents` of a config file
+    fn parse(contents: &str) -> Result<Self, String> {
+        let mut values = HashMap::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {} is not a name = value pair", number + 1))?;
+            let name = name.trim();
+            if !Config::OPTIONS.contains(&name) {
+                return Err(format!("unknown option '{}' on line {}", name, number + 1));
+            }
+
+            values.insert(name.to_owned(), value.trim().to_owned());
+        }
+
+        Ok(Self { values })
+    }
+
+    /// Returns the value of the option `name`, if the config sets it
+    fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+}
+
+/// Parses the value of the alphabet option, which is a comma separated list of bytes
+fn parse_alphabet(alphabet: &str) -> Option<Vec<u8>> {
+    alphabet
+        .split(',')
+        .map(|byte| u8::from_str(byte.trim()).ok())
+        .collect()
+}
+
+/// Parses the value of the padding option, where a pattern is a decimal or `0x` prefixed hexadecimal byte
+fn parse_padding(padding: &str) -> Option<Padding> {
+    match padding {
+        "zeros" => Some(Padding::Zeros),
+        "ones" => Some(Padding::Ones),
+        _ => {
+            let pattern = match padding.strip_prefix("0x") {
+                Some(hex) => u8::from_str_radix(hex, 16).ok()?,
+                None => u8::from_str(padding).ok()?,
+            };
+            Some(Padding::Pattern(pattern))
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a config file with `contents` and returns it, which must outlive the parsing, along with the
+    /// arguments that compress using it
+    fn args_with_config(contents: &str) -> (tempfile::NamedTempFile, Vec<String>) {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), contents).unwrap();
+
+        let args = ["phased_in_codes", "-c", "-i", "input", "-o", "output", "--config", file.path().to_str().unwrap()]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        (file, args)
+    }
+
+    #[test]
+    fn config_fills_in_omitted_options() {
+        let (_config, args) = args_with_config("# defaults\nnum_symbols = 300\nlevel = 9\n");
+        let cli = Cli::from_arg_list(args).unwrap();
+        assert_eq!(cli.num_symbols, Some(300));
+        assert_eq!(cli.level, 9);
+        assert_eq!(cli.options.word_size, WordSize::Bits8);
+    }
+
+    #[test]
+    fn flags_take_precedence_over_config() {
+        let (_config, mut args) = args_with_config("num_symbols = 300\nword_size = 16\n");
+        args.extend(["-s", "12", "--word-size", "8"].iter().map(|arg| arg.to_string()));
+        let cli = Cli::from_arg_list(args).unwrap();
+        assert_eq!(cli.num_symbols, Some(12));
+        assert_eq!(cli.options.word_size, WordSize::Bits8);
+    }
+
+    #[test]
+    fn config_sets_flags() {
+        let (_config, args) = args_with_config("num_symbols = 300\nstore_freq = true\ndensify = true\npayload_checksum = true\n");
+        let cli = Cli::from_arg_list(args).unwrap();
+        assert!(cli.store_freq && cli.densify && cli.payload_checksum);
+
+        let (_config, mut args) = args_with_config("num_symbols = 300\ndensify = false\n");
+        args.extend(["--densify"].iter().map(|arg| arg.to_string()));
+        let cli = Cli::from_arg_list(args).unwrap();
+        assert!(cli.densify && !cli.payload_checksum);
+    }
+}
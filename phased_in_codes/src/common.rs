@@ -2,30 +2,285 @@
 #![allow(non_snake_case)]
 
 use base2::Base2;
+use std::{
+    error::Error,
+    fmt,
+};
 
 /// Represents the parameters used as input to the encoder and the decoder.
 /// The parameters determine the word size that is going to be emitted.
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct PhasedInParams {
-    pub num_symbols: u8,
+    pub num_symbols: u32,
     pub m: u8,
-    pub p: u8,
-    pub P: u8,
+    pub p: u32,
+    pub P: u32,
+    /// Whether the phased-in order is reversed, so that the highest symbols get the short codes
+    pub reverse: bool,
 }
 
 impl PhasedInParams {
-    pub fn new(num_symbols: u8) -> Self {
+    pub fn new(num_symbols: u32) -> Self {
         let m = num_symbols.floor_log2();
-        let p = num_symbols - (1u8 << m);
-        let P = (1u8 << m) - p;
+        let p = num_symbols - (1u32 << m);
+        let P = (1u32 << m) - p;
 
         Self {
             num_symbols,
             m,
             p,
             P,
+            reverse: false,
         }
     }
+
+    /// Sets whether the phased-in order is reversed, so that the highest symbols get the short codes
+    /// instead of the lowest ones. This suits inputs whose high symbols are the most frequent.
+    pub fn with_reverse(mut self, reverse: bool) -> Self {
+        self.reverse = reverse;
+        self
+    }
+
+    /// Returns the position of `symbol` in the phased-in order, which is `symbol` itself unless the order is reversed.
+    /// The position of a position is the symbol again, so this is used both when encoding and when decoding.
+    pub fn rank(&self, symbol: u32) -> u32 {
+        if self.reverse {
+            (self.num_symbols - 1).wrapping_sub(symbol)
+        } else {
+            symbol
+        }
+    }
+
+    /// Creates the parameters of the alphabet with `2^m + p` symbols, which is the inverse of [`new`](PhasedInParams::new).
+    /// Returns an error if `m` doesn't fit a `u32` alphabet or `p` isn't less than `2^m`,
+    /// since `m` is the floor of the base 2 logarithm of the number of symbols.
+    pub fn from_m_p(m: u8, p: u8) -> Result<Self, ParamError> {
+        if m as u32 >= u32::BITS {
+            return Err(ParamError::MTooLarge(m));
+        }
+
+        let p = p as u32;
+        if p >= 1u32 << m {
+            return Err(ParamError::POutOfRange { m, p });
+        }
+
+        Ok(Self {
+            num_symbols: (1u32 << m) + p,
+            m,
+            p,
+            P: (1u32 << m) - p,
+            reverse: false,
+        })
+    }
+
+    /// Returns the minimum and maximum number of bits `num_input_symbols` symbols can be encoded to.
+    /// The minimum assumes every symbol gets a short `m`-bit code and the maximum assumes every
+    /// symbol gets a long `m + 1`-bit code.
+    /// NOTE: When the number of symbols is a power of two there are no long codes, so both bounds are equal.
+    pub fn bits_bounds(&self, num_input_symbols: usize) -> (usize, usize) {
+        let short = self.m as usize;
+        let long = if self.p == 0 { short } else { short + 1 };
+
+        (num_input_symbols * short, num_input_symbols * long)
+    }
+}
+
+/// The errors that can occur while creating [`PhasedInParams`]
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ParamError {
+    /// The number of symbols of the alphabet wouldn't fit a `u32`
+    MTooLarge(u8),
+    /// `p` isn't less than `2^m`
+    POutOfRange { m: u8, p: u32 },
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamError::MTooLarge(m) => write!(f, "m is {}, but it must be less than {}", m, u32::BITS),
+            ParamError::POutOfRange { m, p } => write!(f, "p is {}, but it must be less than 2^{}", p, m),
+        }
+    }
+}
+
+impl Error for ParamError {}
+
+/// The order in which the bits of the encoded stream are packed into bytes
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum BitOrder {
+    /// The first bit of the stream is the most significant bit of the first byte
+    Msb0,
+    /// The first bit of the stream is the least significant bit of the first byte
+    Lsb0,
+}
+
+/// The width of the little endian words the input is split into. Every word is a symbol.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum WordSize {
+    Bits8,
+    Bits16,
+    Bits32,
+}
+
+impl WordSize {
+    /// Returns the WordSize that is `bits` wide, if there is one
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            8 => Some(WordSize::Bits8),
+            16 => Some(WordSize::Bits16),
+            32 => Some(WordSize::Bits32),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of bits in a word
+    pub fn bits(self) -> u8 {
+        match self {
+            WordSize::Bits8 => 8,
+            WordSize::Bits16 => 16,
+            WordSize::Bits32 => 32,
+        }
+    }
+
+    /// Returns the number of bytes in a word
+    pub fn bytes(self) -> usize {
+        self.bits() as usize / 8
+    }
+}
+
+/// The value of the unused bits that pad the last byte of an encoded stream.
+/// The decoder ignores them since the number of unused bits is stored in the header.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub enum Padding {
+    /// Every padding bit is 0
+    #[default]
+    Zeros,
+    /// Every padding bit is 1
+    Ones,
+    /// Every padding bit takes the value of the bit at the same position in the pattern
+    Pattern(u8),
+}
+
+impl Padding {
+    /// Returns the byte the padding bits are taken from
+    pub fn fill_byte(self) -> u8 {
+        match self {
+            Padding::Zeros => 0x00,
+            Padding::Ones => 0xFF,
+            Padding::Pattern(pattern) => pattern,
+        }
+    }
+
+    /// Fills the last `num_unused_bits` bits of the stream in `byte`, which is the last byte of a stream
+    /// packed using `bit_order`, with the padding
+    pub fn apply(self, byte: u8, num_unused_bits: u8, bit_order: BitOrder) -> u8 {
+        if num_unused_bits == 0 {
+            return byte;
+        }
+
+        let num_unused_bits = num_unused_bits as u32;
+        let mask = match bit_order {
+            BitOrder::Msb0 => !0u8 >> (u8::BITS - num_unused_bits),
+            BitOrder::Lsb0 => !0u8 << (u8::BITS - num_unused_bits),
+        };
+
+        (byte & !mask) | (self.fill_byte() & mask)
+    }
+}
+
+/// Options that change how the input is turned into symbols and how the encoded bits are stored.
+/// The encoder writes them in the [`Header`](crate::header::Header) so the decoder can mirror them.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct StreamOptions {
+    pub bit_order: BitOrder,
+    pub word_size: WordSize,
+    /// The value subtracted from every word to get its symbol
+    pub offset: u32,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            bit_order: BitOrder::Msb0,
+            word_size: WordSize::Bits8,
+            offset: 0,
+        }
+    }
+}
+
+impl StreamOptions {
+    /// Splits `bytes` into words and turns each word into a symbol.
+    /// Trailing bytes that don't make up a whole word are ignored.
+    pub fn symbols_from_bytes(&self, bytes: &[u8]) -> Vec<u32> {
+        bytes
+            .chunks_exact(self.word_size.bytes())
+            .map(|word| self.symbol_from_word(word))
+            .collect()
+    }
+
+    /// Turns the bytes of a single `word` into its symbol
+    pub fn symbol_from_word(&self, word: &[u8]) -> u32 {
+        let mut le_bytes = [0u8; 4];
+        le_bytes[..word.len()].copy_from_slice(word);
+        u32::from_le_bytes(le_bytes).wrapping_sub(self.offset)
+    }
+
+    /// Returns whether the word of `symbol` fits in the word size, so that none of its bytes are lost
+    pub fn word_fits(&self, symbol: u32) -> bool {
+        let word = symbol.wrapping_add(self.offset);
+        match self.word_size {
+            WordSize::Bits32 => true,
+            word_size => word >> word_size.bits() == 0,
+        }
+    }
+
+    /// Turns `symbol` back to its word and appends the word's bytes to `bytes`
+    pub fn push_symbol_bytes(&self, symbol: u32, bytes: &mut Vec<u8>) {
+        let word = symbol.wrapping_add(self.offset).to_le_bytes();
+        bytes.extend_from_slice(&word[..self.word_size.bytes()]);
+    }
+
+    /// Turns `symbol` back to its word and writes the word's bytes to `bytes`, which must be exactly a word long
+    pub fn write_symbol_bytes(&self, symbol: u32, bytes: &mut [u8]) {
+        let word = symbol.wrapping_add(self.offset).to_le_bytes();
+        bytes.copy_from_slice(&word[..self.word_size.bytes()]);
+    }
+}
+
+/// The surrogate code points, which are not Unicode scalar values and so never appear in a `char`
+const SURROGATES: std::ops::RangeInclusive<u32> = 0xD800..=0xDFFF;
+
+/// The number of surrogate code points
+const NUM_SURROGATES: u32 = 0x800;
+
+/// Returns the symbol of the Unicode scalar value `c`.
+/// Symbols are the code points with the surrogate gap closed, so that no symbol is wasted on it.
+pub fn symbol_from_char(c: char) -> u32 {
+    let code_point = c as u32;
+    if code_point > *SURROGATES.end() {
+        code_point - NUM_SURROGATES
+    } else {
+        code_point
+    }
+}
+
+/// Reverses [`symbol_from_char`]. Returns `None` if `symbol` is past the last Unicode scalar value.
+pub fn char_from_symbol(symbol: u32) -> Option<char> {
+    if symbol >= *SURROGATES.start() {
+        char::from_u32(symbol.checked_add(NUM_SURROGATES)?)
+    } else {
+        char::from_u32(symbol)
+    }
+}
+
+/// Counts how many times each of the `num_symbols` symbols appears in `symbols`
+pub fn symbol_frequencies(symbols: &[u32], num_symbols: u32) -> Vec<u64> {
+    let mut frequencies = vec![0u64; num_symbols as usize];
+    for symbol in symbols {
+        frequencies[*symbol as usize] += 1;
+    }
+
+    frequencies
 }
 
 #[cfg(test)]
@@ -40,8 +295,50 @@ mod tests {
             m: 3,
             p: 1,
             P: 7,
+            reverse: false,
         };
 
         assert_eq!(params, expected);
     }
+
+    #[test]
+    fn params_from_m_p_works() {
+        assert_eq!(PhasedInParams::from_m_p(3, 1), Ok(PhasedInParams::new(9)));
+        assert_eq!(PhasedInParams::from_m_p(8, 0), Ok(PhasedInParams::new(256)));
+        assert_eq!(PhasedInParams::from_m_p(3, 8), Err(ParamError::POutOfRange { m: 3, p: 8 }));
+        assert_eq!(PhasedInParams::from_m_p(32, 0), Err(ParamError::MTooLarge(32)));
+    }
+
+    #[test]
+    fn bits_bounds_works() {
+        assert_eq!(PhasedInParams::new(9).bits_bounds(100), (300, 400));
+        assert_eq!(PhasedInParams::new(8).bits_bounds(100), (300, 300));
+        assert_eq!(PhasedInParams::new(9).bits_bounds(0), (0, 0));
+    }
+
+    #[test]
+    fn char_symbols_skip_the_surrogates() {
+        assert_eq!(symbol_from_char('\u{D7FF}'), 0xD7FF);
+        assert_eq!(symbol_from_char('\u{E000}'), 0xD800);
+        assert_eq!(char_from_symbol(0xD800), Some('\u{E000}'));
+        assert_eq!(char_from_symbol(symbol_from_char(char::MAX)), Some(char::MAX));
+        assert_eq!(char_from_symbol(symbol_from_char(char::MAX) + 1), None);
+    }
+
+    #[test]
+    fn stream_options_symbols_round_trip() {
+        let options = StreamOptions {
+            word_size: WordSize::Bits16,
+            offset: 1000,
+            ..StreamOptions::default()
+        };
+
+        let bytes: &[u8] = &[0xE8, 0x03, 0xE9, 0x03, 0x10, 0x27];
+        let symbols = options.symbols_from_bytes(bytes);
+        assert_eq!(symbols, vec![0, 1, 9000]);
+
+        let mut round_trip = Vec::new();
+        symbols.iter().for_each(|symbol| options.push_symbol_bytes(*symbol, &mut round_trip));
+        assert_eq!(round_trip, bytes);
+    }
 }
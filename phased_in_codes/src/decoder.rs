@@ -1,76 +1,613 @@
 //! Contains the [`Decoder`] that will decode bytes using the Phased-In Codes algorithm
 
 use crate::{
-    common::PhasedInParams,
+    common::{
+        char_from_symbol,
+        PhasedInParams,
+        StreamOptions,
+    },
     encoder::EncodedStream,
+    header::{
+        Header,
+        SparseIndex,
+    },
+    pipeline::Preset,
 };
 
 use bitvec::{
     slice::BitSlice,
     order::Msb0,
+    view::BitView,
 };
+use std::{
+    convert::TryFrom,
+    error::Error,
+    fmt,
+    io,
+};
+
+/// The errors that can occur while decoding a compressed file
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum DecodeError {
+    /// The input ended before the whole header could be read
+    TruncatedHeader,
+    /// The input doesn't start with [`header::MAGIC`](crate::header::MAGIC)
+    InvalidMagic,
+    /// The input is a pack of files written by [`pack`](crate::pack::pack), which has to be read using
+    /// [`unpack`](crate::pack::unpack)
+    PackedFile,
+    /// The file was written using a format version this decoder doesn't understand
+    UnsupportedVersion(u8),
+    /// A varint in the header is malformed or doesn't fit in 64 bits
+    InvalidVarint,
+    /// The header says the alphabet has no symbols, so nothing could have been encoded with it
+    EmptyAlphabet,
+    /// The header contains a word size other than 8, 16 or 32 bits
+    InvalidWordSize(u8),
+    /// The header contains an unknown preset id
+    InvalidPreset(u8),
+    /// The header doesn't match the parameters or options the decoder was configured with
+    HeaderMismatch,
+    /// The encoded bits ended in the middle of a symbol, or before all the expected symbols were decoded
+    UnexpectedEndOfStream,
+    /// The header says that more bits of the last byte are unused than there are in a byte, or in the payload
+    InvalidUnusedBits(u8),
+    /// The payload has more bits than can be addressed
+    PayloadTooLarge,
+    /// The length prefix of the payload is malformed or doesn't fit in 64 bits
+    InvalidLengthPrefix,
+    /// The payload is said to hold `count` symbols, but its bits can hold at most `max_count` symbols
+    ImplausibleLength { count: usize, max_count: usize },
+    /// A decoded symbol doesn't fit in the alphabet it has to be re-encoded with
+    SymbolOutOfRange { symbol: u32, num_symbols: u32 },
+    /// The word of a decoded symbol is wider than the word size, so writing it would lose some of its bytes
+    WordOverflow { symbol: u32, word_bits: u8 },
+    /// A decoded symbol isn't the symbol of any Unicode scalar value
+    InvalidChar(u32),
+    /// The compressed file couldn't be read
+    Io(io::ErrorKind),
+    /// The encoded bytes don't match the checksum stored in the header, so they were corrupted after being written
+    PayloadChecksumMismatch { expected: u32, actual: u32 },
+    /// The alphabet has a single symbol, whose code is 0 bits long, so the number of symbols can only come from
+    /// a length prefix, but the payload has none and isn't empty
+    UncountableSymbols,
+    /// The decoded output is longer or shorter than the `expected` number of bytes
+    OutputLengthMismatch { expected: usize },
+    /// The last `num_bits` zero bits of a headerless payload could be either padding or whole symbols
+    AmbiguousPadding { num_bits: u8 },
+    /// The header lists a transform id that is neither built-in nor one of the given transforms
+    UnknownTransform(u8),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::TruncatedHeader => write!(f, "the input ended before the end of the header"),
+            DecodeError::InvalidMagic => write!(f, "the input is not a phased-in codes file"),
+            DecodeError::PackedFile => write!(f, "the input is a pack of files, which has to be unpacked"),
+            DecodeError::UnsupportedVersion(version) => write!(f, "unsupported file format version {}", version),
+            DecodeError::InvalidVarint => write!(f, "the header contains an invalid varint"),
+            DecodeError::EmptyAlphabet => write!(f, "the header says the alphabet has no symbols"),
+            DecodeError::InvalidWordSize(bits) => write!(f, "the header contains an invalid word size of {} bits", bits),
+            DecodeError::InvalidPreset(id) => write!(f, "the header contains an unknown preset {}", id),
+            DecodeError::HeaderMismatch => write!(f, "the header doesn't match the parameters of the decoder"),
+            DecodeError::UnexpectedEndOfStream => write!(f, "the encoded stream ended in the middle of a symbol"),
+            DecodeError::InvalidUnusedBits(bits) => {
+                write!(f, "the header says {} bits of the last byte are unused, which is more than there are", bits)
+            }
+            DecodeError::PayloadTooLarge => write!(f, "the payload has more bits than can be addressed"),
+            DecodeError::InvalidLengthPrefix => write!(f, "the payload starts with an invalid length prefix"),
+            DecodeError::ImplausibleLength { count, max_count } => {
+                write!(f, "the payload is said to hold {} symbols, but it can hold at most {}", count, max_count)
+            }
+            DecodeError::SymbolOutOfRange { symbol, num_symbols } => {
+                write!(f, "symbol {} doesn't fit in an alphabet of {} symbols", symbol, num_symbols)
+            }
+            DecodeError::PayloadChecksumMismatch { expected, actual } => write!(
+                f,
+                "the encoded bytes are corrupted, their checksum is {:08x} instead of {:08x}",
+                actual, expected
+            ),
+            DecodeError::WordOverflow { symbol, word_bits } => {
+                write!(f, "the word of symbol {} doesn't fit in {} bits", symbol, word_bits)
+            }
+            DecodeError::InvalidChar(symbol) => write!(f, "symbol {} is not a Unicode scalar value", symbol),
+            DecodeError::Io(kind) => write!(f, "could not read the compressed file: {}", io::Error::from(*kind)),
+            DecodeError::UncountableSymbols => {
+                write!(f, "the codes of a single symbol alphabet are empty, so the payload needs a length prefix")
+            }
+            DecodeError::OutputLengthMismatch { expected } => {
+                write!(f, "the decoded output isn't {} bytes long as expected", expected)
+            }
+            DecodeError::AmbiguousPadding { num_bits } => {
+                write!(f, "the last {} bits of the payload could be either padding or symbols", num_bits)
+            }
+            DecodeError::UnknownTransform(id) => write!(f, "the input was transformed by unknown transform {}", id),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+impl From<DecodeError> for io::Error {
+    fn from(error: DecodeError) -> Self {
+        match error {
+            DecodeError::Io(kind) => io::Error::from(kind),
+            _ => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
+
+/// What [`decode_raw`] does with the bits at the end of a payload, which might be padding rather than a symbol.
+///
+/// Padding is shorter than a byte and fills the end of the last byte, and every policy assumes it's the
+/// default [`Padding::Zeros`](crate::common::Padding::Zeros). So the only bits that are ambiguous are fewer
+/// than 8 zeros at the end of the payload whose length is a multiple of `m`, since they are also codes of the symbol 0.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum TrailingPolicy {
+    /// The bits are inferred to be padding if they are too few to make up a code, or if they are fewer than 8 zeros.
+    /// NOTE: This means that symbols whose codes are all zeros can't end the payload.
+    IgnorePadding,
+    /// If the bits are too few to make up a code, they are completed with zeros and decoded as one last symbol
+    TreatAsSymbol,
+    /// [`DecodeError::UnexpectedEndOfStream`] is returned if the bits are too few to make up a code
+    Error,
+    /// Like [`IgnorePadding`](TrailingPolicy::IgnorePadding), but [`DecodeError::AmbiguousPadding`] is returned
+    /// instead of inferring the bits are padding when they could be symbols as well, and
+    /// [`DecodeError::UnexpectedEndOfStream`] is returned if the bits are too few to make up a code but aren't zeros
+    RejectAmbiguous,
+}
 
 /// The phased-in decoder
 pub struct Decoder {
-    params: PhasedInParams
+    params: PhasedInParams,
+    options: StreamOptions,
 }
 
 impl Decoder {
     /// Creates a new Encoder with decoding parameters `params`
     pub fn new(params: PhasedInParams) -> Self {
-        Self { params }
+        Self {
+            params,
+            options: StreamOptions::default(),
+        }
+    }
+
+    /// Creates a Decoder configured solely from the parameters and options stored in `header`
+    pub fn from_header(header: &Header) -> Self {
+        Self::new(header.params()).with_options(header.options)
+    }
+
+    /// Sets the [`StreamOptions`] the decoded streams were encoded with
+    pub fn with_options(mut self, options: StreamOptions) -> Self {
+        self.options = options;
+        self
     }
 
-    /// Helper function to create a byte from a number of bits
-    fn byte_from_bitslice(bitslice: &BitSlice<Msb0, u8>) -> u8 {
-        let mut res = 0u8;
+    /// Checks that `header` describes a stream this decoder can decode.
+    /// Streams compressed with any preset other than [`Preset::Plain`], or densified, deinterleaved or transformed
+    /// before compressing them, have to be decompressed using [`pipeline::decompress`](crate::pipeline::decompress).
+    fn check_header(&self, header: &Header) -> Result<(), DecodeError> {
+        if header.num_symbols != self.params.num_symbols
+            || header.reverse != self.params.reverse
+            || header.options != self.options
+            || header.preset != Preset::Plain
+            || header.dense_map.is_some()
+            || header.channels.is_some_and(|channels| channels != 1)
+            || !header.transforms.is_empty()
+        {
+            return Err(DecodeError::HeaderMismatch);
+        }
+
+        Ok(())
+    }
+
+    /// Helper function to create a symbol from a number of bits
+    fn symbol_from_bitslice(bitslice: &BitSlice<Msb0, u8>) -> u32 {
+        let mut res = 0u32;
         for bit in bitslice {
-            res <<= 1u8;
-            res |= *bit as u8;
+            res <<= 1u32;
+            res |= *bit as u32;
         }
 
         res
     }
 
+    /// Decodes the symbol whose code starts at `cursor`.
+    /// Returns the symbol along with the position right after its code.
+    fn decode_symbol(&self, bits: &BitSlice<Msb0, u8>, cursor: usize) -> Result<(u32, usize), DecodeError> {
+        let end = cursor + self.params.m as usize;
+        let next_m_bits = bits.get(cursor..end).ok_or(DecodeError::UnexpectedEndOfStream)?;
+
+        let symbol = Decoder::symbol_from_bitslice(next_m_bits);
+        if symbol >= self.params.P {
+            let next_bit = *bits.get(end).ok_or(DecodeError::UnexpectedEndOfStream)? as u32;
+            let rank = self.params.P + ((symbol - self.params.P) * 2) + next_bit;
+            Ok((self.params.rank(rank), end + 1))
+        } else {
+            Ok((self.params.rank(symbol), end))
+        }
+    }
+
+    /// Appends the bytes of the word of `symbol` to `bytes`.
+    /// Returns [`DecodeError::WordOverflow`] instead of writing part of the word if it's wider than the word size.
+    fn push_word(&self, symbol: u32, bytes: &mut Vec<u8>) -> Result<(), DecodeError> {
+        if !self.options.word_fits(symbol) {
+            let word_bits = self.options.word_size.bits();
+            return Err(DecodeError::WordOverflow { symbol, word_bits });
+        }
+
+        self.options.push_symbol_bytes(symbol, bytes);
+        Ok(())
+    }
+
+    /// Checks that the number of symbols in `bits`, which have no length prefix, can be told by decoding them.
+    /// The codes of an alphabet with a single symbol are 0 bits long, so decoding them would never reach the end.
+    fn check_countable(&self, bits: &BitSlice<Msb0, u8>) -> Result<(), DecodeError> {
+        if self.params.m == 0 && !bits.is_empty() {
+            return Err(DecodeError::UncountableSymbols);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the largest number of symbols `bits` can hold, since every code is at least `m` bits long
+    fn max_symbols(&self, bits: &BitSlice<Msb0, u8>) -> usize {
+        bits.len() / (self.params.m.max(1) as usize)
+    }
+
+    /// Returns the capacity needed for the bytes of `num_symbols` decoded symbols,
+    /// never trusting `num_symbols` more than the number of symbols `bits` can hold
+    fn decoded_capacity(&self, bits: &BitSlice<Msb0, u8>, num_symbols: usize) -> usize {
+        num_symbols.min(self.max_symbols(bits)) * self.options.word_size.bytes()
+    }
+
+    /// Decodes the length prefix written by [`length_prefix_symbols`](crate::encoder::length_prefix_symbols)
+    /// from the start of `bits`. Returns the number of symbols along with the position right after the prefix.
+    fn decode_length_prefix(&self, bits: &BitSlice<Msb0, u8>) -> Result<(u64, usize), DecodeError> {
+        let mut num_digits = 1u32;
+        let mut cursor = 0usize;
+
+        loop {
+            let (symbol, next) = self.decode_symbol(bits, cursor)?;
+            cursor = next;
+
+            match symbol {
+                0 => break,
+                1 if num_digits < u64::BITS => num_digits += 1,
+                _ => return Err(DecodeError::InvalidLengthPrefix),
+            }
+        }
+
+        let mut count = 0u64;
+        for _ in 0..num_digits {
+            let (digit, next) = self.decode_symbol(bits, cursor)?;
+            cursor = next;
+
+            count = count
+                .checked_mul(self.params.num_symbols as u64)
+                .and_then(|count| count.checked_add(digit as u64))
+                .ok_or(DecodeError::InvalidLengthPrefix)?;
+        }
+
+        Ok((count, cursor))
+    }
+
     /// Decodes an encoded `stream` and returns a [`Vec`] of bytes.
-    /// The bytes are the original symbols that were encoded using [`Encoder`]
-    pub fn decode_stream(&self, stream: &EncodedStream) -> Vec<u8> {
+    /// The bytes are the original words that were encoded using [`Encoder`]
+    /// NOTE: A stream of a single symbol alphabet can only be decoded if it has a length prefix or is empty.
+    pub fn decode_stream(&self, stream: &EncodedStream) -> Result<Vec<u8>, DecodeError> {
         let bits = stream.bits();
-        let mut decoded_bytes = Vec::with_capacity(bits.len() * 8usize);
+        if stream.header().length_prefix {
+            let (count, start) = self.decode_length_prefix(bits)?;
+            let count = usize::try_from(count).map_err(|_| DecodeError::InvalidLengthPrefix)?;
+            return self.decode_count(&bits[start..], count);
+        }
+
+        self.check_countable(bits)?;
+        let mut decoded_bytes = Vec::with_capacity(self.decoded_capacity(bits, usize::MAX));
         let mut cursor = 0usize;
 
         while cursor != bits.len() {
-            let next_m_bits = &bits[cursor..cursor + self.params.m as usize];
-            cursor += self.params.m as usize;
+            let (decoded_symbol, next) = self.decode_symbol(bits, cursor)?;
+            cursor = next;
 
-            let symbol = Decoder::byte_from_bitslice(next_m_bits);
-            let decoded_symbol = if symbol >= self.params.P {
-                let next_bit = bits[cursor];
-                let next_bit = if next_bit { 1 } else { 0 };
-                cursor += 1;
-                self.params.P + ((symbol - self.params.P) * 2) + next_bit
-            } else {
-                symbol
-            };
+            self.push_word(decoded_symbol, &mut decoded_bytes)?;
+        }
 
-            decoded_bytes.push(decoded_symbol);
+        Ok(decoded_bytes)
+    }
+
+    /// Decodes exactly `count` symbols from the start of `bits`, ignoring any bits after them.
+    /// A `count` that `bits` can't possibly hold is rejected before decoding anything.
+    /// NOTE: The codes of an alphabet with a single symbol are 0 bits long, so any `count` is plausible for it.
+    fn decode_count(&self, bits: &BitSlice<Msb0, u8>, count: usize) -> Result<Vec<u8>, DecodeError> {
+        let max_count = self.max_symbols(bits);
+        if self.params.m > 0 && count > max_count {
+            return Err(DecodeError::ImplausibleLength { count, max_count });
         }
 
-        decoded_bytes
+        let mut decoded_bytes = Vec::with_capacity(self.decoded_capacity(bits, count));
+        let mut cursor = 0usize;
+
+        for _ in 0..count {
+            let (decoded_symbol, next) = self.decode_symbol(bits, cursor)?;
+            cursor = next;
+
+            self.push_word(decoded_symbol, &mut decoded_bytes)?;
+        }
+
+        Ok(decoded_bytes)
+    }
+
+    /// Returns the position of the first bit of the code of the symbol at `symbol_index` in `stream`,
+    /// or `None` if the stream has no symbol at that index. The codes before it are walked, but not decoded.
+    /// NOTE: The length prefix of the stream, if there is one, isn't counted as symbols.
+    pub fn bit_offset_of(&self, stream: &EncodedStream, symbol_index: usize) -> Option<usize> {
+        let bits = stream.bits();
+        let mut cursor = 0usize;
+        if stream.header().length_prefix {
+            let (count, start) = self.decode_length_prefix(bits).ok()?;
+            if symbol_index as u64 >= count {
+                return None;
+            }
+
+            cursor = start;
+        }
+
+        for _ in 0..symbol_index {
+            cursor = self.decode_symbol(bits, cursor).ok()?.1;
+        }
+
+        if cursor < bits.len() {
+            Some(cursor)
+        } else {
+            None
+        }
+    }
+
+    /// Decodes a `stream` that was encoded using [`Encoder::encode_str`] and returns the string.
+    /// The decoder must be configured like the stream, for example using [`Decoder::from_header`].
+    pub fn decode_to_string(&self, stream: &EncodedStream) -> Result<String, DecodeError> {
+        let bytes = self.decode_stream(stream)?;
+        self.options
+            .symbols_from_bytes(&bytes)
+            .into_iter()
+            .map(|symbol| char_from_symbol(symbol).ok_or(DecodeError::InvalidChar(symbol)))
+            .collect()
+    }
+
+    /// Builds a [`SparseIndex`] with a point every `interval` symbols of `stream`, walking the codes of its
+    /// payload without decoding their words
+    pub(crate) fn sparse_index(&self, stream: &EncodedStream, interval: u64) -> Result<SparseIndex, DecodeError> {
+        let bits = stream.bits();
+        let (count, mut cursor) = if stream.header().length_prefix {
+            let (count, start) = self.decode_length_prefix(bits)?;
+            (Some(count), start)
+        } else {
+            self.check_countable(bits)?;
+            (None, 0)
+        };
+
+        let mut bit_offsets = Vec::new();
+        let mut symbol = 0u64;
+        while count.map_or(cursor != bits.len(), |count| symbol != count) {
+            if symbol > 0 && symbol.is_multiple_of(interval) {
+                bit_offsets.push(cursor as u64);
+            }
+
+            cursor = self.decode_symbol(bits, cursor)?.1;
+            symbol += 1;
+        }
+
+        Ok(SparseIndex { interval, bit_offsets })
+    }
+
+    /// Decodes the symbols in `[start, end)` of an encoded `stream` and returns their bytes.
+    /// Decoding starts at the nearest point of the [`SparseIndex`](crate::header::SparseIndex) of the stream before
+    /// `start`, or at the first symbol if there is none. The symbols before `start` are decoded to find where
+    /// the range begins, but their bytes aren't kept.
+    /// Returns [`DecodeError::UnexpectedEndOfStream`] if the range goes past the end of the stream.
+    pub(crate) fn decode_range(&self, stream: &EncodedStream, start: usize, end: usize) -> Result<Vec<u8>, DecodeError> {
+        let bits = stream.bits();
+        let point = stream.header().index.as_ref().and_then(|index| index.seek(start as u64));
+        let (first, mut cursor) = match point {
+            Some((symbol, bit_offset)) => (symbol as usize, usize::try_from(bit_offset).unwrap_or(usize::MAX)),
+            None if stream.header().length_prefix => (0, self.decode_length_prefix(bits)?.1),
+            None => (0, 0),
+        };
+
+        for _ in first..start {
+            cursor = self.decode_symbol(bits, cursor)?.1;
+        }
+
+        let rest = bits.get(cursor..).ok_or(DecodeError::UnexpectedEndOfStream)?;
+        self.decode_count(rest, end.saturating_sub(start)).map_err(|error| match error {
+            // The count comes from the caller instead of the stream, so there are simply not enough symbols
+            DecodeError::ImplausibleLength { .. } => DecodeError::UnexpectedEndOfStream,
+            error => error,
+        })
     }
 
     /// Decodes a slice of bytes that were encoded using [`Encoder`].
+    /// Returns [`DecodeError::HeaderMismatch`] if the file was encoded with different parameters or options.
     /// NOTE: This slice of bytes must have the same structure as the one
     /// dumped by [`encoder::EncodedStream::write_to_file`] function.
-    pub fn decode_bytes(&self, bytes: &[u8]) -> Vec<u8> {
-        self.decode_stream(&EncodedStream::from_encoded_bytes(bytes))
+    pub fn decode_bytes(&self, bytes: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let stream = EncodedStream::from_encoded_bytes(bytes)?;
+        self.check_header(stream.header())?;
+
+        self.decode_stream(&stream)
+    }
+
+    /// Decodes `stream` straight into the file at `path`, which is created with a length of `expected_len` bytes
+    /// and memory-mapped, so that the decoded bytes are never held on the heap.
+    /// Returns [`DecodeError::OutputLengthMismatch`] if the stream doesn't decode to exactly `expected_len` bytes.
+    /// NOTE: The file is removed if decoding fails, instead of being left half-written.
+    #[cfg(feature = "mmap")]
+    pub fn decode_to_file_mmap<P: AsRef<std::path::Path>>(
+        &self,
+        stream: &EncodedStream,
+        path: P,
+        expected_len: usize,
+    ) -> Result<(), DecodeError> {
+        let path = path.as_ref();
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .map_err(|error| DecodeError::Io(error.kind()))?;
+
+        let result = self.decode_into_file(stream, &file, expected_len);
+        if result.is_err() {
+            drop(file);
+            // The decoding error is the one worth reporting, even if the file can't be removed
+            let _ = std::fs::remove_file(path);
+        }
+        result
+    }
+
+    /// Decodes `stream` into `file`, which is resized to `expected_len` bytes and memory-mapped
+    #[cfg(feature = "mmap")]
+    fn decode_into_file(
+        &self,
+        stream: &EncodedStream,
+        file: &std::fs::File,
+        expected_len: usize,
+    ) -> Result<(), DecodeError> {
+        let io_error = |error: io::Error| DecodeError::Io(error.kind());
+        file.set_len(expected_len as u64).map_err(io_error)?;
+
+        let bits = stream.bits();
+        let (count, mut cursor) = if stream.header().length_prefix {
+            let (count, start) = self.decode_length_prefix(bits)?;
+            (Some(count), start)
+        } else {
+            self.check_countable(bits)?;
+            (None, 0)
+        };
+
+        let word_bytes = self.options.word_size.bytes();
+        let mismatch = DecodeError::OutputLengthMismatch { expected: expected_len };
+        if count.is_some_and(|count| count.checked_mul(word_bytes as u64) != Some(expected_len as u64)) {
+            return Err(mismatch);
+        }
+
+        // Mapping an empty file fails, and there is nothing to decode into anyway
+        if expected_len == 0 {
+            return if count.is_some() || cursor == bits.len() { Ok(()) } else { Err(mismatch) };
+        }
+
+        // SAFETY: The file was just created by `decode_to_file_mmap` and nothing else is expected to modify it while it's mapped
+        let mut output = unsafe { memmap2::MmapMut::map_mut(file) }.map_err(io_error)?;
+        let mut words = output.chunks_exact_mut(word_bytes);
+        let done = |cursor: usize, num_decoded: u64| match count {
+            Some(count) => num_decoded == count,
+            None => cursor == bits.len(),
+        };
+
+        let mut num_decoded = 0u64;
+        while !done(cursor, num_decoded) {
+            let (decoded_symbol, next) = self.decode_symbol(bits, cursor)?;
+            cursor = next;
+            num_decoded += 1;
+
+            if !self.options.word_fits(decoded_symbol) {
+                let word_bits = self.options.word_size.bits();
+                return Err(DecodeError::WordOverflow { symbol: decoded_symbol, word_bits });
+            }
+
+            let word = words.next().ok_or_else(|| mismatch.clone())?;
+            self.options.write_symbol_bytes(decoded_symbol, word);
+        }
+
+        if num_decoded * word_bytes as u64 != expected_len as u64 {
+            return Err(mismatch);
+        }
+
+        output.flush().map_err(io_error)
     }
 }
 
+/// Decodes exactly `count` symbols from `bytes`, which are the encoded bits without any [`Header`].
+/// This is useful for formats that store the number of symbols on their own.
+/// The bits after the last symbol are ignored, so the padding of the last byte can be anything.
+pub fn decode_payload(bytes: &[u8], count: usize, params: PhasedInParams) -> Result<Vec<u8>, DecodeError> {
+    Decoder::new(params).decode_count(bytes.view_bits::<Msb0>(), count)
+}
+
+/// Decodes every symbol of `bytes`, which are the encoded bits without any [`Header`] or number of symbols.
+/// Since the end of the last symbol isn't known, the bits after it are handled according to `policy`.
+/// Returns [`DecodeError::UncountableSymbols`] if `params` have a single symbol and `bytes` aren't empty.
+pub fn decode_raw(bytes: &[u8], params: PhasedInParams, policy: TrailingPolicy) -> Result<Vec<u8>, DecodeError> {
+    let decoder = Decoder::new(params);
+    let bits = bytes.view_bits::<Msb0>();
+    decoder.check_countable(bits)?;
+    let mut decoded_bytes = Vec::with_capacity(decoder.decoded_capacity(bits, usize::MAX));
+    let mut cursor = 0usize;
+
+    while cursor != bits.len() {
+        let rest = &bits[cursor..];
+        if rest.len() < u8::BITS as usize && rest.not_any() {
+            match policy {
+                TrailingPolicy::IgnorePadding => break,
+                TrailingPolicy::RejectAmbiguous if rest.len() % (decoder.params.m.max(1) as usize) == 0 => {
+                    return Err(DecodeError::AmbiguousPadding { num_bits: rest.len() as u8 })
+                }
+                TrailingPolicy::RejectAmbiguous => break,
+                TrailingPolicy::TreatAsSymbol | TrailingPolicy::Error => (),
+            }
+        }
+
+        let decoded_symbol = match decoder.decode_symbol(bits, cursor) {
+            Ok((decoded_symbol, next)) => {
+                cursor = next;
+                decoded_symbol
+            }
+            Err(DecodeError::UnexpectedEndOfStream) => match policy {
+                TrailingPolicy::IgnorePadding => break,
+                TrailingPolicy::TreatAsSymbol => {
+                    let mut padded = rest.to_bitvec();
+                    padded.resize(decoder.params.m as usize + 1, false);
+                    cursor = bits.len();
+                    decoder.decode_symbol(&padded, 0)?.0
+                }
+                TrailingPolicy::Error | TrailingPolicy::RejectAmbiguous => return Err(DecodeError::UnexpectedEndOfStream),
+            },
+            Err(error) => return Err(error),
+        };
+
+        decoder.push_word(decoded_symbol, &mut decoded_bytes)?;
+    }
+
+    Ok(decoded_bytes)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::encoder::Encoder;
+    use crate::{
+        common::{
+            BitOrder,
+            WordSize,
+        },
+        encoder::{
+            length_prefix_symbols,
+            Encoder,
+            PhasedInEncodeExt,
+        },
+        header::read_header,
+        pipeline::{
+            self,
+            CompressionSettings,
+        },
+        transforms::{
+            Mtf,
+            Transform,
+        },
+    };
 
     #[test]
     fn decode_stream_works() {
@@ -82,7 +619,272 @@ mod tests {
         let encoded_stream = encoder.encode_bytes(bytes);
 
         let decoder = Decoder::new(params);
-        let decoded_bytes = decoder.decode_stream(&encoded_stream);
+        let decoded_bytes = decoder.decode_stream(&encoded_stream).unwrap();
         assert_eq!(bytes, decoded_bytes.as_slice());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn decode_to_string_round_trips() {
+        let text = "phased-in κώδικες, 符号 and 🦀\u{E000}";
+        let stream = Encoder::new(PhasedInParams::new(2)).encode_str(text);
+        assert_eq!(stream.header().num_symbols, 0x1F980 - 0x800 + 1);
+
+        let decoder = Decoder::from_header(stream.header());
+        assert_eq!(decoder.decode_to_string(&stream).unwrap(), text);
+    }
+
+    #[test]
+    fn wide_words_decode_to_whole_words() {
+        let options = StreamOptions {
+            word_size: WordSize::Bits16,
+            ..StreamOptions::default()
+        };
+        let words: Vec<u8> = (0..300u16).flat_map(|word| (word * 7).to_le_bytes().to_vec()).collect();
+
+        let mut encoder = Encoder::new(PhasedInParams::new(2100)).with_options(options).with_length_prefix(true);
+        encoder.compute_encoded_symbols();
+        let mut file = Vec::new();
+        encoder.encode_bytes(&words).write_to(&mut file).unwrap();
+
+        let decoder = Decoder::new(PhasedInParams::new(2100)).with_options(options);
+        let decoded = decoder.decode_bytes(&file).unwrap();
+        assert_eq!(decoded.len(), 2 * 300);
+        assert_eq!(decoded, words);
+
+        let truncated = &file[..file.len() - 1];
+        assert_eq!(decoder.decode_bytes(truncated), Err(DecodeError::UnexpectedEndOfStream));
+    }
+
+    #[test]
+    fn words_wider_than_the_word_size_are_rejected() {
+        let options = StreamOptions {
+            word_size: WordSize::Bits16,
+            offset: 10000,
+            ..StreamOptions::default()
+        };
+        let decoder = Decoder::new(PhasedInParams::new(70000)).with_options(options);
+
+        let stream = EncodedStream::from_codes(&[(55535, 16)]);
+        assert_eq!(decoder.decode_stream(&stream), Ok(vec![0xFF, 0xFF]));
+
+        let stream = EncodedStream::from_codes(&[(55535, 16), (55536, 16)]);
+        assert_eq!(
+            decoder.decode_stream(&stream),
+            Err(DecodeError::WordOverflow { symbol: 55536, word_bits: 16 })
+        );
+    }
+
+    #[test]
+    fn decode_stream_from_explicit_codes() {
+        let decoder = Decoder::new(PhasedInParams::new(9));
+
+        let stream = EncodedStream::from_codes(&[(0b000, 3), (0b110, 3), (0b1110, 4), (0b1111, 4)]);
+        assert_eq!(decoder.decode_stream(&stream).unwrap(), vec![0, 6, 7, 8]);
+
+        let truncated = EncodedStream::from_codes(&[(0b101, 3), (0b11, 2)]);
+        assert_eq!(decoder.decode_stream(&truncated), Err(DecodeError::UnexpectedEndOfStream));
+
+        let unfinished_long_code = EncodedStream::from_codes(&[(0b111, 3)]);
+        assert_eq!(decoder.decode_stream(&unfinished_long_code), Err(DecodeError::UnexpectedEndOfStream));
+
+        // The code is extended with zeros, so these are 12 codes of 0 followed by 0b1111
+        let wide = EncodedStream::from_codes(&[(0b1111, 40)]);
+        assert_eq!(wide.bits().len(), 40);
+        assert_eq!(decoder.decode_stream(&wide).unwrap(), [vec![0; 12], vec![8]].concat());
+        assert_eq!(EncodedStream::from_codes(&[(u32::MAX, u8::MAX)]).bits().count_ones(), 32);
+    }
+
+    #[test]
+    fn decode_raw_ignores_padding() {
+        // 01 10 111 followed by a single padding bit, and 111 111 followed by two padding bits
+        assert_eq!(decode_raw(&[0b0110_1110], PhasedInParams::new(5), TrailingPolicy::IgnorePadding), Ok(vec![1, 2, 4]));
+        assert_eq!(decode_raw(&[0b1111_1100], PhasedInParams::new(5), TrailingPolicy::IgnorePadding), Ok(vec![4, 4]));
+    }
+
+    #[test]
+    fn decode_raw_treats_trailing_bits_as_symbol() {
+        assert_eq!(decode_raw(&[0b0110_1110], PhasedInParams::new(5), TrailingPolicy::TreatAsSymbol), Ok(vec![1, 2, 4, 0]));
+        assert_eq!(decode_raw(&[0b1111_1100], PhasedInParams::new(5), TrailingPolicy::TreatAsSymbol), Ok(vec![4, 4, 0]));
+        assert_eq!(decode_raw(&[0b0110_1111], PhasedInParams::new(5), TrailingPolicy::TreatAsSymbol), Ok(vec![1, 2, 4, 2]));
+    }
+
+    #[test]
+    fn decode_raw_errors_on_incomplete_codes() {
+        assert_eq!(
+            decode_raw(&[0b0110_1110], PhasedInParams::new(5), TrailingPolicy::Error),
+            Err(DecodeError::UnexpectedEndOfStream)
+        );
+        assert_eq!(decode_raw(&[0b1111_1100], PhasedInParams::new(5), TrailingPolicy::Error), Ok(vec![4, 4, 0]));
+    }
+
+    #[test]
+    fn decode_raw_rejects_ambiguous_padding() {
+        let params = PhasedInParams::new(5);
+        let policy = TrailingPolicy::RejectAmbiguous;
+
+        // A single zero bit is too short to be a 2-bit code, so it can only be padding
+        assert_eq!(decode_raw(&[0b0110_1110], params.clone(), policy), Ok(vec![1, 2, 4]));
+        // Two zero bits are either padding or the code of 0
+        assert_eq!(decode_raw(&[0b1111_1100], params.clone(), policy), Err(DecodeError::AmbiguousPadding { num_bits: 2 }));
+        // Trailing ones can't be padding
+        assert_eq!(decode_raw(&[0b0110_1111], params, policy), Err(DecodeError::UnexpectedEndOfStream));
+    }
+
+    #[test]
+    fn single_symbol_payloads_need_a_length_prefix() {
+        let params = PhasedInParams::new(1);
+        let file = EncodedStream::from_codes(&[(0b1010_1010, 8)])
+            .with_params(&params, StreamOptions::default())
+            .to_contiguous_file_bytes();
+        assert_eq!(read_header(&file).unwrap().num_symbols, 1);
+
+        assert_eq!(pipeline::decompress(&file), Err(DecodeError::UncountableSymbols));
+        assert_eq!(decode_raw(&[0b1010_1010], params.clone(), TrailingPolicy::Error), Err(DecodeError::UncountableSymbols));
+        assert_eq!(decode_raw(&[], params.clone(), TrailingPolicy::Error), Ok(vec![]));
+
+        let empty = EncodedStream::from_codes(&[]).with_params(&params, StreamOptions::default());
+        assert_eq!(pipeline::decompress(&empty.to_contiguous_file_bytes()), Ok(vec![]));
+    }
+
+    #[test]
+    fn bit_offset_of_points_at_the_symbol() {
+        let bytes: Vec<u8> = (0..9).rev().chain(0..9).collect();
+        let params = PhasedInParams::new(9);
+
+        for length_prefix in [false, true].iter() {
+            let stream = Encoder::new(params.clone()).with_length_prefix(*length_prefix).encode_bytes(&bytes);
+            let decoder = Decoder::new(params.clone());
+
+            for (index, byte) in bytes.iter().enumerate() {
+                let offset = decoder.bit_offset_of(&stream, index).unwrap();
+                assert_eq!(decoder.decode_symbol(stream.bits(), offset).unwrap().0, *byte as u32, "symbol {}", index);
+            }
+            assert_eq!(decoder.bit_offset_of(&stream, bytes.len()), None);
+            assert_eq!(decoder.bit_offset_of(&stream, usize::MAX), None);
+        }
+    }
+
+    #[test]
+    fn decoder_from_header_mirrors_the_encoder_options() {
+        let bytes: Vec<_> = (100..110).chain((100..110).rev()).collect();
+        let options = StreamOptions {
+            bit_order: BitOrder::Lsb0,
+            word_size: WordSize::Bits8,
+            offset: 100,
+        };
+
+        let mut encoder = Encoder::new(PhasedInParams::new(10)).with_options(options);
+        encoder.compute_encoded_symbols();
+        let mut file = Vec::new();
+        encoder.encode_bytes(&bytes).write_to(&mut file).unwrap();
+
+        let header = read_header(&file).unwrap();
+        let decoder = Decoder::from_header(&header);
+        assert_eq!(decoder.decode_bytes(&file).unwrap(), bytes);
+
+        let mismatched = Decoder::new(PhasedInParams::new(10));
+        assert_eq!(mismatched.decode_bytes(&file), Err(DecodeError::HeaderMismatch));
+    }
+
+    #[test]
+    fn files_that_need_the_pipeline_are_rejected() {
+        let input: Vec<u8> = (0..30).map(|i| [3, 10, 200][i % 3]).collect();
+        let settings = CompressionSettings {
+            densify: true,
+            ..CompressionSettings::new(PhasedInParams::new(201))
+        };
+        let mut file = Vec::new();
+        pipeline::compress(&input, &settings).unwrap().write_to(&mut file).unwrap();
+
+        let decoder = Decoder::from_header(&read_header(&file).unwrap());
+        assert_eq!(decoder.decode_bytes(&file), Err(DecodeError::HeaderMismatch));
+        assert_eq!(pipeline::decompress(&file).unwrap(), input);
+
+        let input: Vec<u8> = (0..30).map(|i| (i % 3) as u8).collect();
+        let settings = CompressionSettings {
+            channels: 3,
+            ..CompressionSettings::new(PhasedInParams::new(3))
+        };
+        let stream = pipeline::compress(&input, &settings).unwrap();
+        assert_eq!(stream.header().preset, Preset::Plain);
+        let file = stream.to_contiguous_file_bytes();
+
+        let decoder = Decoder::from_header(&read_header(&file).unwrap());
+        assert_eq!(decoder.decode_bytes(&file), Err(DecodeError::HeaderMismatch));
+        assert_eq!(pipeline::decompress(&file).unwrap(), input);
+
+        let transforms: Vec<Box<dyn Transform>> = vec![Box::new(Mtf)];
+        let settings = CompressionSettings::new(PhasedInParams::new(3));
+        let stream = pipeline::compress_with_transforms(&input, &settings, &transforms).unwrap();
+        assert_eq!(stream.header().preset, Preset::Plain);
+        let file = stream.to_contiguous_file_bytes();
+
+        let decoder = Decoder::from_header(&read_header(&file).unwrap());
+        assert_eq!(decoder.decode_bytes(&file), Err(DecodeError::HeaderMismatch));
+        assert_eq!(pipeline::decompress(&file).unwrap(), input);
+    }
+
+    #[test]
+    fn implausible_lengths_are_rejected() {
+        let params = PhasedInParams::new(4);
+        let prefix: Vec<u8> = length_prefix_symbols(1 << 40, 4).unwrap().iter().map(|symbol| *symbol as u8).collect();
+        let stream = Encoder::new(params.clone()).encode_bytes(&prefix).with_length_prefix();
+
+        let expected = DecodeError::ImplausibleLength { count: 1 << 40, max_count: 0 };
+        assert_eq!(Decoder::new(params.clone()).decode_stream(&stream), Err(expected));
+
+        // An all-zeros payload of 128 bits holds at most 64 2-bit symbols
+        let zeros = [0u8; 16];
+        let expected = DecodeError::ImplausibleLength { count: 1000, max_count: 64 };
+        assert_eq!(decode_payload(&zeros, 1000, params.clone()), Err(expected));
+        assert_eq!(decode_payload(&zeros, 64, params), Ok(vec![0u8; 64]));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_decode_matches_decode_stream() {
+        let bytes: Vec<u8> = (0..3000u32).flat_map(|i| ((i * 7) % 1000).to_le_bytes()[..2].to_vec()).collect();
+        let options = StreamOptions {
+            word_size: WordSize::Bits16,
+            ..StreamOptions::default()
+        };
+
+        for length_prefix in [false, true].iter() {
+            let stream = Encoder::new(PhasedInParams::new(1000))
+                .with_options(options)
+                .with_length_prefix(*length_prefix)
+                .encode_bytes(&bytes);
+            let decoder = Decoder::from_header(stream.header());
+            let file = tempfile::NamedTempFile::new().unwrap();
+
+            decoder.decode_to_file_mmap(&stream, file.path(), bytes.len()).unwrap();
+            assert_eq!(std::fs::read(file.path()).unwrap(), decoder.decode_stream(&stream).unwrap());
+
+            let expected = DecodeError::OutputLengthMismatch { expected: bytes.len() - 2 };
+            assert_eq!(decoder.decode_to_file_mmap(&stream, file.path(), bytes.len() - 2), Err(expected));
+            assert!(!file.path().exists());
+        }
+
+        let params = PhasedInParams::new(1);
+        let stream = EncodedStream::from_codes(&[(0b1010_1010, 8)]).with_params(&params, StreamOptions::default());
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let result = Decoder::new(params).decode_to_file_mmap(&stream, file.path(), 8);
+        assert_eq!(result, Err(DecodeError::UncountableSymbols));
+    }
+
+    #[test]
+    fn decode_payload_ignores_trailing_bits() {
+        let symbols: Vec<u8> = (0..9).chain(0..4).collect();
+        let params = PhasedInParams::new(9);
+        let mut payload: Vec<u8> = symbols.iter().phased_in_encode(&params).collect();
+
+        // 13 symbols take 41 bits, so fill the 7 padding bits of the last byte and add a garbage byte
+        *payload.last_mut().unwrap() |= 0b0111_1111;
+        payload.push(0xA5);
+
+        assert_eq!(decode_payload(&payload, symbols.len(), params.clone()).unwrap(), symbols);
+        assert_eq!(decode_payload(&payload, 3, params.clone()).unwrap(), &symbols[..3]);
+        let expected = DecodeError::ImplausibleLength { count: 100, max_count: 18 };
+        assert_eq!(decode_payload(&payload, 100, params), Err(expected));
+    }
+}
@@ -1,29 +1,131 @@
 //! Contains the Encoder as well as any structures that the encoder might use such as EncodedStream
 
-use crate::common::PhasedInParams;
-use bitvec::{
-    mem::BitMemory,
-    prelude::*,
+use crate::{
+    common::{
+        symbol_from_char,
+        symbol_frequencies,
+        BitOrder,
+        Padding,
+        PhasedInParams,
+        StreamOptions,
+        WordSize,
+    },
+    decoder::{
+        DecodeError,
+        Decoder,
+    },
+    header::{
+        self,
+        Header,
+        SparseIndex,
+    },
+    pipeline::{
+        self,
+        Preset,
+    },
 };
+use bitvec::prelude::*;
 use std::{
+    error::Error,
+    fmt,
     fs,
     io::{
+        self,
         prelude::*,
         BufWriter,
     },
+    borrow::Borrow,
+    convert::TryFrom,
     path::Path,
 };
 
+/// The errors that can occur while encoding
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum EncodeError {
+    /// The input contains a byte that isn't part of the alphabet it has to be encoded with
+    NotInAlphabet(u8),
+    /// The input contains a word wider than a byte whose symbol doesn't fit in an alphabet of `num_symbols` symbols
+    SymbolOutOfRange { symbol: u32, num_symbols: u32 },
+    /// The encoded bits don't fit in the output buffer
+    BufferTooSmall,
+    /// The encoded bits don't end on a byte boundary, so the last byte would need `padding_bits` bits of padding
+    NotByteAligned { padding_bits: u8 },
+    /// A length prefix was requested, but an alphabet of `num_symbols` symbols can't encode one
+    AlphabetTooSmall { num_symbols: u32 },
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::NotInAlphabet(byte) => write!(f, "the input contains {}, which is not part of the alphabet", byte),
+            EncodeError::SymbolOutOfRange { symbol, num_symbols } => {
+                write!(f, "symbol {} doesn't fit in an alphabet of {} symbols", symbol, num_symbols)
+            }
+            EncodeError::BufferTooSmall => write!(f, "the encoded bits don't fit in the output buffer"),
+            EncodeError::NotByteAligned { padding_bits } => {
+                write!(f, "the encoded bits don't end on a byte boundary, {} bits of padding are needed", padding_bits)
+            }
+            EncodeError::AlphabetTooSmall { num_symbols } => {
+                write!(f, "a length prefix needs at least 2 symbols, but the alphabet has {}", num_symbols)
+            }
+        }
+    }
+}
+
+impl Error for EncodeError {}
+
+impl From<EncodeError> for io::Error {
+    fn from(error: EncodeError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, error)
+    }
+}
+
+/// The errors that can occur while merging encoded streams
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum MergeError {
+    /// The streams turn words into symbols differently, so their bits can't be decoded as a single stream
+    IncompatibleAlphabets,
+    /// The stream was compressed using a preset whose transforms can't be spliced
+    UnsupportedPreset(Preset),
+    /// The stream starts with a length prefix, which would end up in the middle of the merged stream
+    LengthPrefixed,
+    /// The input of the stream was deinterleaved as a whole, so it can't be continued by another stream
+    Deinterleaved,
+    /// The input of the stream went through [`Transform`](crate::transforms::Transform)s as a whole,
+    /// so it can't be continued by another stream
+    Transformed,
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeError::IncompatibleAlphabets => write!(f, "the streams were encoded using different alphabets"),
+            MergeError::UnsupportedPreset(preset) => write!(f, "streams compressed with {:?} can't be merged", preset),
+            MergeError::LengthPrefixed => write!(f, "streams that start with a length prefix can't be merged"),
+            MergeError::Deinterleaved => write!(f, "streams of deinterleaved channels can't be merged"),
+            MergeError::Transformed => write!(f, "streams of transformed input can't be merged"),
+        }
+    }
+}
+
+impl Error for MergeError {}
+
 /// The phased-in encoder
 pub struct Encoder {
     params: PhasedInParams,
+    options: StreamOptions,
     encoded_symbols: Vec<EncodedSymbol>,
+    store_frequencies: bool,
+    length_prefix: bool,
+    padding: Padding,
+    require_byte_alignment: bool,
+    index_interval: Option<u64>,
 }
 
-/// This is an encoded symbol that the [`Encoder`] emits after processing a byte
+/// This is an encoded symbol that the [`Encoder`] emits after processing a word
 #[derive(Debug, Eq, PartialEq, Clone)]
 struct EncodedSymbol {
-    symbol: u8,
+    symbol: u32,
     num_bits_encoded: u8,
 }
 
@@ -31,32 +133,191 @@ struct EncodedSymbol {
 #[derive(Debug, Eq, PartialEq)]
 pub struct EncodedStream {
     stream: BitVec<Msb0, u8>,
+    header: Header,
+    padding: Padding,
+    payload_checksum: bool,
 }
 
 impl EncodedSymbol {
-    /// Creates a new EncodedSymbol from a byte based on the number of bits that were encoded for this byte.
-    fn new(symbol: u8, num_bits_encoded: u8) -> Self {
+    /// Creates a new EncodedSymbol from a word based on the number of bits that were encoded for this word.
+    fn new(symbol: u32, num_bits_encoded: u8) -> Self {
         Self { symbol, num_bits_encoded }
     }
 
-    /// Converts this symbol to bits
-    fn to_bitvec(&self) -> BitVec<Msb0, u8> {
-        let start = (u8::BITS - self.num_bits_encoded) as usize;
-        self.symbol.view_bits()[start..].to_bitvec()
+    /// Appends the bits of this symbol, which are the low `num_bits_encoded` bits of `symbol`, to `stream`.
+    /// NOTE: `extend_from_bitslice` needs the same storage type on both sides, so the symbol is viewed as big-endian bytes.
+    fn append_to(&self, stream: &mut BitVec<Msb0, u8>) {
+        let start = (u32::BITS - self.num_bits_encoded as u32) as usize;
+        let bytes = self.symbol.to_be_bytes();
+        stream.extend_from_bitslice(&bytes.view_bits::<Msb0>()[start..]);
     }
 }
 
 impl EncodedStream {
     /// Creates a new EncodedStream from a [`Vec`] of [`EncodedSymbol`]s.
-    /// This basically accumulates all the bits from all the encoded symbols to a single [`BitVec`]
+    /// This basically copies the bits of all the encoded symbols to a single [`BitVec`],
+    /// which is allocated once with the exact number of bits.
     fn new(symbols: Vec<EncodedSymbol>) -> Self {
-        let buffer = BitVec::with_capacity(symbols.len() * u8::BITS as usize);
-        let stream = symbols.iter().fold(buffer, |mut acc, s| {
-            acc.extend_from_bitslice(s.to_bitvec().as_bitslice());
-            acc
+        let num_bits = symbols.iter().map(|s| s.num_bits_encoded as usize).sum();
+        let mut stream = BitVec::with_capacity(num_bits);
+        for s in &symbols {
+            s.append_to(&mut stream);
+        }
+
+        Self::from_bitvec(stream)
+    }
+
+    /// Creates an EncodedStream from explicit `(code, width)` pairs, where the low `width` bits of `code`
+    /// are appended to the stream, without going through the symbol table of an [`Encoder`].
+    /// This allows feeding arbitrary bit patterns, even malformed ones, to the [`Decoder`].
+    /// NOTE: `code` is extended with zeros up to `width`, so a `width` of more than 32 bits appends zeros
+    /// followed by the 32 bits of `code` instead of being rejected.
+    pub fn from_codes(codes: &[(u32, u8)]) -> Self {
+        let code_bits = u32::BITS as u8;
+        let symbols = codes.iter().flat_map(|&(code, width)| {
+            let zeros = width.saturating_sub(code_bits);
+            (0..zeros)
+                .step_by(code_bits as usize)
+                .map(move |start| EncodedSymbol::new(0, (zeros - start).min(code_bits)))
+                .chain(std::iter::once(EncodedSymbol::new(code, width.min(code_bits))))
         });
 
-        Self { stream }
+        Self::new(symbols.collect())
+    }
+
+    /// Wraps `stream` into an EncodedStream with a default [`Header`]
+    fn from_bitvec(mut stream: BitVec<Msb0, u8>) -> Self {
+        stream.set_uninitialized(false);
+        let header = Header {
+            num_unused_bits: num_unused_bits(stream.len()),
+            ..Header::default()
+        };
+
+        Self {
+            stream,
+            header,
+            padding: Padding::default(),
+            payload_checksum: false,
+        }
+    }
+
+    /// Records the parameters and options the stream was encoded with in its header
+    pub(crate) fn with_params(mut self, params: &PhasedInParams, options: StreamOptions) -> Self {
+        self.header.num_symbols = params.num_symbols;
+        self.header.reverse = params.reverse;
+        self.header.options = options;
+        self
+    }
+
+    /// Records the [`Preset`] the stream was compressed with in its header
+    pub(crate) fn with_preset(mut self, preset: Preset) -> Self {
+        self.header.preset = preset;
+        self
+    }
+
+    /// Stores the frequency of each symbol in the header of the stream
+    pub(crate) fn with_frequencies(mut self, frequencies: Vec<u64>) -> Self {
+        self.header.frequencies = Some(frequencies);
+        self
+    }
+
+    /// Records in the header of the stream that its payload starts with a length prefix
+    pub(crate) fn with_length_prefix(mut self) -> Self {
+        self.header.length_prefix = true;
+        self
+    }
+
+    /// Records in the header of the stream the number of channels its input was deinterleaved into
+    pub(crate) fn with_channels(mut self, channels: u32) -> Self {
+        self.header.channels = Some(channels);
+        self
+    }
+
+    /// Stores the [`SparseIndex`] of the stream in its header
+    pub(crate) fn with_index(mut self, index: SparseIndex) -> Self {
+        self.header.index = Some(index);
+        self
+    }
+
+    /// Records in the header of the stream the ids of the transforms its input went through, in order
+    pub(crate) fn with_transforms(mut self, transforms: Vec<u8>) -> Self {
+        self.header.transforms = transforms;
+        self
+    }
+
+    /// Stores the map that reverses [`densify`](crate::transforms::densify) in the header of the stream
+    pub(crate) fn with_dense_map(mut self, dense_map: Vec<u8>) -> Self {
+        self.header.dense_map = Some(dense_map);
+        self
+    }
+
+    /// Stores the map from the encoded symbols to the original bytes in the header of the stream
+    pub(crate) fn with_symbol_map(mut self, symbol_map: Vec<u8>) -> Self {
+        self.header.symbol_map = Some(symbol_map);
+        self
+    }
+
+    /// Appends the bits of `other` to this stream, so that decoding the result yields the input of this stream
+    /// followed by the input of `other`. The frequencies are summed if both streams store them, and the
+    /// [`SparseIndex`] covers the whole result if either stream has one.
+    ///
+    /// NOTE: Only streams with the same alphabet and options that were compressed using the
+    /// [`Stored`](Preset::Stored) or [`Plain`](Preset::Plain) preset can be merged, as the other
+    /// presets carry state across the whole input, as do deinterleaving and the [`Transform`](crate::transforms::Transform)s.
+    pub fn append(&mut self, other: &EncodedStream) -> Result<(), MergeError> {
+        for preset in [self.header.preset, other.header.preset].iter() {
+            if !matches!(preset, Preset::Stored | Preset::Plain) {
+                return Err(MergeError::UnsupportedPreset(*preset));
+            }
+        }
+
+        if self.header.length_prefix || other.header.length_prefix {
+            return Err(MergeError::LengthPrefixed);
+        }
+
+        if self.header.channels.is_some() || other.header.channels.is_some() {
+            return Err(MergeError::Deinterleaved);
+        }
+
+        if !self.header.transforms.is_empty() || !other.header.transforms.is_empty() {
+            return Err(MergeError::Transformed);
+        }
+
+        merge_params(&self.header.params(), &other.header.params())?;
+        if self.header.preset != other.header.preset
+            || self.header.options != other.header.options
+            || self.header.dense_map != other.header.dense_map
+        {
+            return Err(MergeError::IncompatibleAlphabets);
+        }
+
+        self.stream.extend_from_bitslice(&other.stream);
+        self.stream.set_uninitialized(false);
+        self.header.num_unused_bits = num_unused_bits(self.stream.len());
+
+        let frequencies = self.header.frequencies.take().zip(other.header.frequencies.as_ref());
+        self.header.frequencies = frequencies.map(|(left, right)| left.iter().zip(right).map(|(l, r)| l + r).collect());
+
+        // The points of `other` are off by the symbols of this stream, which aren't counted anywhere,
+        // so the index is rebuilt over the merged payload instead
+        let interval = self.header.index.as_ref().or(other.header.index.as_ref()).map(|index| index.interval);
+        let index = interval.and_then(|interval| Decoder::from_header(&self.header).sparse_index(self, interval).ok());
+        self.header.index = index;
+
+        Ok(())
+    }
+
+    /// Sets the [`Padding`] that fills the unused bits of the last byte when the stream is written
+    pub fn with_padding(mut self, padding: Padding) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Sets whether the checksum of the encoded bytes is stored in the header when the stream is written,
+    /// so that corruption is caught before decoding it
+    pub fn with_payload_checksum(mut self, payload_checksum: bool) -> Self {
+        self.payload_checksum = payload_checksum;
+        self
     }
 
     /// Returns a reference to the underlying [`BitVec`]
@@ -64,49 +325,143 @@ impl EncodedStream {
         &self.stream
     }
 
+    /// Returns whether decompressing the stream using [`pipeline::decompress`] yields exactly `expected`,
+    /// undoing the transforms of its preset
+    pub fn verify_decodes_to(&self, expected: &[u8]) -> bool {
+        let mut file = Vec::new();
+        self.write_to(&mut file).is_ok() && pipeline::decompress(&file).is_ok_and(|decoded| decoded == expected)
+    }
+
+    /// Returns a reference to the [`Header`] that is written in front of the stream
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
     /// Constructs an EncodedStream from a slice of bytes
     ///
     /// NOTE: The slice of bytes is expected to be in the same structure as the stream is
-    /// written to a file using [`write_to_file`]. That is, the first byte denotes the number
-    /// of bits were not used in the last byte and the rest of the bytes are the encoded ones.
-    pub fn from_encoded_bytes(bytes: &[u8]) -> Self {
-        let num_unused_bits = bytes[0] as usize;
-        let num_used_bits = (bytes.len() - 1) * u8::BITS as usize - num_unused_bits;
-
-        let stream = unsafe {
-            let mut bits = BitSlice::from_slice_unchecked(&bytes[1..]).to_bitvec();
-            bits.set_len(num_used_bits);
-            bits
-        };
+    /// written to a file using [`write_to_file`]. That is, a [`Header`] followed by the encoded bytes.
+    /// If the header contains the checksum of the encoded bytes, they are verified before anything else.
+    pub fn from_encoded_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let (header, header_len) = Header::read(bytes)?;
+        if let Some(expected) = header.payload_crc {
+            let actual = crc32fast::hash(&bytes[header_len..]);
+            if actual != expected {
+                return Err(DecodeError::PayloadChecksumMismatch { expected, actual });
+            }
+        }
+
+        let mut bytes = bytes[header_len..].to_vec();
+        let num_unused_bits = header.num_unused_bits;
+        let num_used_bits = bytes
+            .len()
+            .checked_mul(u8::BITS as usize)
+            .and_then(|num_bits| num_bits.checked_sub(num_unused_bits as usize))
+            .filter(|_| (num_unused_bits as u32) < u8::BITS)
+            .ok_or(DecodeError::InvalidUnusedBits(num_unused_bits))?;
+
+        if header.options.bit_order == BitOrder::Lsb0 {
+            bytes.iter_mut().for_each(|byte| *byte = byte.reverse_bits());
+        }
+
+        let mut stream = BitSlice::<Msb0, u8>::from_slice(&bytes)
+            .ok_or(DecodeError::PayloadTooLarge)?
+            .to_bitvec();
+        stream.truncate(num_used_bits);
+
+        Ok(Self {
+            stream,
+            payload_checksum: header.payload_crc.is_some(),
+            header,
+            padding: Padding::default(),
+        })
+    }
 
-        Self { stream }
+    /// Reads the file at `path`, which has the structure written by [`write_to_file`], into an EncodedStream
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Self, DecodeError> {
+        let bytes = fs::read(path).map_err(|error| DecodeError::Io(error.kind()))?;
+        Self::from_encoded_bytes(&bytes)
     }
 
     /// Constructs an EncodedStream from a slice of bytes
+    ///
+    /// NOTE: Panics if `bytes` has more bits than a [`BitSlice`] can address, which takes more than 2^61 bytes
+    /// on 64-bit targets. Use [`from_encoded_bytes`](EncodedStream::from_encoded_bytes) for untrusted input.
     pub fn from_bytes(bytes: &[u8]) -> Self {
-        let stream = unsafe {
-            BitSlice::from_slice_unchecked(bytes).to_bitvec()
-        };
+        let stream = BitSlice::<Msb0, u8>::from_slice(bytes)
+            .expect("The bytes have more bits than a BitSlice can address")
+            .to_bitvec();
 
-        Self { stream }
+        Self::from_bitvec(stream)
     }
 
     /// Writes the EncodedStream to the file by the given `path`.
     /// The contents of the `path` will be overwritten by the encoded stream.
     /// This function will write the following information to the file:
     ///
-    /// First byte:        The number of bits that were not used from the last byte of the stream
+    /// Header:            The [`Header`] of the stream
     /// Rest of the bytes: The encoded bytes
     pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
         let mut writer = BufWriter::new(fs::File::create(path.as_ref())?);
-        let num_bits_unused = (self.stream.capacity() - self.stream.len()) as u8;
-
-        writer.write(&num_bits_unused.to_le_bytes())?;
-        writer.write(self.stream.as_slice())?;
+        self.write_to(&mut writer)?;
         writer.flush()?;
 
         Ok(())
     }
+
+    /// Writes the EncodedStream to `writer` using the same structure as [`write_to_file`]
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.to_contiguous_file_bytes())
+    }
+
+    /// Writes the bytes of [`to_contiguous_file_bytes`](EncodedStream::to_contiguous_file_bytes) to `writer` as a single frame, preceded by their length
+    /// as 4 big endian bytes. Frames can be read back one at a time using [`read_length_delimited`].
+    ///
+    /// NOTE: Returns an error of kind [`InvalidInput`](io::ErrorKind::InvalidInput) if the file is 4 GiB or larger.
+    pub fn write_length_delimited<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let bytes = self.to_contiguous_file_bytes();
+        let len = u32::try_from(bytes.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "the file doesn't fit in a frame"))?;
+
+        writer.write_all(&len.to_be_bytes())?;
+        writer.write_all(&bytes)
+    }
+
+    /// Returns the [`Header`] followed by the encoded bytes in a single buffer,
+    /// which are the exact bytes [`write_to_file`] writes
+    pub fn to_contiguous_file_bytes(&self) -> Vec<u8> {
+        let payload = self.payload_bytes();
+        let mut bytes = Vec::with_capacity(payload.len() + 32);
+
+        let written = if self.payload_checksum {
+            let header = Header {
+                payload_crc: Some(crc32fast::hash(&payload)),
+                ..self.header.clone()
+            };
+            header.write_to(&mut bytes)
+        } else {
+            self.header.write_to(&mut bytes)
+        };
+        written.expect("Writing to a Vec can't fail");
+
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    /// Returns the encoded bytes as they are written after the header, that is packed according
+    /// to the [`BitOrder`] of the stream and with the unused bits of the last byte filled with its [`Padding`]
+    pub(crate) fn payload_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.stream.as_slice().to_vec();
+        if self.header.options.bit_order == BitOrder::Lsb0 {
+            bytes.iter_mut().for_each(|byte| *byte = byte.reverse_bits());
+        }
+
+        if let Some(last) = bytes.last_mut() {
+            *last = self.padding.apply(*last, self.header.num_unused_bits, self.header.options.bit_order);
+        }
+
+        bytes
+    }
 }
 
 impl Encoder {
@@ -114,10 +469,57 @@ impl Encoder {
     pub fn new(params: PhasedInParams) -> Self {
         Self {
             params: params.clone(),
+            options: StreamOptions::default(),
             encoded_symbols: Vec::with_capacity(params.num_symbols as usize),
+            store_frequencies: false,
+            length_prefix: false,
+            padding: Padding::default(),
+            require_byte_alignment: false,
+            index_interval: None,
         }
     }
 
+    /// Sets the [`StreamOptions`] used to turn the input into symbols and to store the encoded bits
+    pub fn with_options(mut self, options: StreamOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets whether the frequency of each symbol is stored in the header of the encoded streams
+    pub fn with_frequencies(mut self, store_frequencies: bool) -> Self {
+        self.store_frequencies = store_frequencies;
+        self
+    }
+
+    /// Sets whether the encoded streams start with the number of encoded symbols.
+    /// The number is encoded using the same phased-in codes as the data, see [`length_prefix_symbols`].
+    /// NOTE: The length prefix needs an alphabet of at least 2 symbols, otherwise
+    /// [`try_encode_bytes`](Encoder::try_encode_bytes) returns [`EncodeError::AlphabetTooSmall`].
+    pub fn with_length_prefix(mut self, length_prefix: bool) -> Self {
+        self.length_prefix = length_prefix;
+        self
+    }
+
+    /// Sets the [`Padding`] that fills the unused bits of the last byte of the encoded streams
+    pub fn with_padding(mut self, padding: Padding) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Sets the number of symbols between the points of the [`SparseIndex`] stored in the header of the encoded streams,
+    /// or `None` to store no index. An interval of 0 is the same as `None`.
+    pub fn with_index_interval(mut self, index_interval: Option<u64>) -> Self {
+        self.index_interval = index_interval.filter(|interval| *interval != 0);
+        self
+    }
+
+    /// Sets whether the encoded bits have to end on a byte boundary. When they do, [`try_encode_bytes`](Encoder::try_encode_bytes)
+    /// and [`encode_into_slice`](Encoder::encode_into_slice) return [`EncodeError::NotByteAligned`] instead of padding the last byte.
+    pub fn with_byte_alignment_required(mut self, require_byte_alignment: bool) -> Self {
+        self.require_byte_alignment = require_byte_alignment;
+        self
+    }
+
     pub fn compute_encoded_symbols(&mut self) {
         for symbol in 0..self.params.num_symbols {
             let encoded = self.encode_symbol(symbol);
@@ -125,46 +527,615 @@ impl Encoder {
         }
     }
 
-    /// Encodes a slice of bytes and returns an `EncodedStream`
+    /// Encodes a slice of bytes and returns an `EncodedStream`.
+    /// The bytes are split into words according to the [`StreamOptions`] of the encoder.
     ///
     /// # Example
     ///
     /// ```
-    /// use phased_in_codes::common:*;
-    /// use phased_in_codes::encoder::*;
+    /// use phased_in_codes::prelude::*;
     ///
     /// let bytes: &[u8] = &[0, 1, 2, 3, 4, 5];
-    /// let encoder = Encoder::new(PhasedInParams::new(6));
+    /// let mut encoder = Encoder::new(PhasedInParams::new(6));
+    /// encoder.compute_encoded_symbols();
     /// let encoded_stream = encoder.encode_bytes(bytes);
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if a word doesn't fit in the alphabet, or if a length prefix is requested with an alphabet of less
+    /// than 2 symbols. Use [`try_encode_bytes`](Encoder::try_encode_bytes) for untrusted input.
     pub fn encode_bytes(&self, bytes: &[u8]) -> EncodedStream {
-        let encoded = bytes.iter().map(|b| self.encoded_symbols[*b as usize].clone()).collect();
-        EncodedStream::new(encoded)
+        self.encode_symbols(&self.options.symbols_from_bytes(bytes))
     }
 
-    /// Encodes a single byte (symbol) and returns an [`EncodedSymbol`]
-    /// Which holds the encoded byte as well as the number of bits used to encode it
-    fn encode_symbol(&self, symbol: u8) -> EncodedSymbol {
-        let mut mask = !0u8;
-        mask >>= u8::BITS - self.params.m;
+    /// Like [`encode_bytes`](Encoder::encode_bytes), but returns an error instead of panicking if a word doesn't fit
+    /// in the alphabet, see [`check_alphabet`](Encoder::check_alphabet), or if the alphabet is too small for the
+    /// length prefix, which is [`EncodeError::AlphabetTooSmall`].
+    /// Returns [`EncodeError::NotByteAligned`] if byte alignment is [required](Encoder::with_byte_alignment_required)
+    /// and the encoded bits don't end on a byte boundary.
+    pub fn try_encode_bytes(&self, bytes: &[u8]) -> Result<EncodedStream, EncodeError> {
+        if self.length_prefix && self.params.num_symbols < 2 {
+            return Err(EncodeError::AlphabetTooSmall { num_symbols: self.params.num_symbols });
+        }
+        self.check_alphabet(bytes)?;
+        let stream = self.encode_bytes(bytes);
+        let padding_bits = stream.header.num_unused_bits;
+        if self.require_byte_alignment && padding_bits != 0 {
+            return Err(EncodeError::NotByteAligned { padding_bits });
+        }
+
+        Ok(stream)
+    }
+
+    /// Encodes the Unicode scalar values of `s` as 32-bit words, using a symbol per scalar value as
+    /// given by [`symbol_from_char`]. The other settings of this encoder are kept.
+    /// Use [`Decoder::decode_to_string`] to get the string back.
+    ///
+    /// NOTE: The parameters of this encoder are ignored, as the alphabet is sized to the largest symbol of `s`.
+    pub fn encode_str(&self, s: &str) -> EncodedStream {
+        let symbols: Vec<u32> = s.chars().map(symbol_from_char).collect();
+        let num_symbols = symbols.iter().max().map_or(2, |max| (max + 1).max(2));
+        let options = StreamOptions {
+            word_size: WordSize::Bits32,
+            ..StreamOptions::default()
+        };
+
+        Encoder::new(PhasedInParams::new(num_symbols))
+            .with_options(options)
+            .with_frequencies(self.store_frequencies)
+            .with_length_prefix(self.length_prefix)
+            .with_padding(self.padding)
+            .encode_symbols(&symbols)
+    }
 
-        let (encoded_symbol, num_bits_encoded) = if symbol >= self.params.P {
-            let mut encoded_symbol = self.params.P + ((symbol - self.params.P) / 2u8);
-            encoded_symbol &= mask;
-            encoded_symbol = (encoded_symbol << 1u8) | ((symbol - self.params.P) & 1u8);
-            (encoded_symbol, self.params.m + 1u8)
+    /// Encodes `symbols` into an `EncodedStream`, using the codes of [`compute_encoded_symbols`](Encoder::compute_encoded_symbols)
+    /// if they were computed and computing every code as it's needed otherwise
+    fn encode_symbols(&self, symbols: &[u32]) -> EncodedStream {
+        let prefix = if self.length_prefix {
+            length_prefix_symbols(symbols.len() as u64, self.params.num_symbols)
+                .expect("The length prefix needs at least 2 symbols")
         } else {
-            (symbol & mask, self.params.m)
+            Vec::new()
+        };
+
+        let encoded: Vec<EncodedSymbol> = prefix
+            .iter()
+            .chain(symbols.iter())
+            .map(|s| self.code_of(*s).expect("Every symbol has to fit in the alphabet"))
+            .collect();
+        let index = self.index_interval.map(|interval| {
+            let mut bit_offset = 0u64;
+            let mut bit_offsets = Vec::new();
+            for (position, code) in encoded.iter().enumerate() {
+                let symbol = position.wrapping_sub(prefix.len()) as u64;
+                if position > prefix.len() && symbol.is_multiple_of(interval) {
+                    bit_offsets.push(bit_offset);
+                }
+                bit_offset += code.num_bits_encoded as u64;
+            }
+
+            SparseIndex { interval, bit_offsets }
+        });
+
+        let mut stream = EncodedStream::new(encoded)
+            .with_params(&self.params, self.options)
+            .with_padding(self.padding);
+        if self.length_prefix {
+            stream = stream.with_length_prefix();
+        }
+        if let Some(index) = index {
+            stream = stream.with_index(index);
+        }
+
+        if self.store_frequencies {
+            stream.with_frequencies(symbol_frequencies(symbols, self.params.num_symbols))
+        } else {
+            stream
+        }
+    }
+
+    /// Returns an error for the first word of `bytes` whose symbol doesn't fit in the alphabet of the encoder.
+    /// That is [`EncodeError::NotInAlphabet`] for 8-bit words and [`EncodeError::SymbolOutOfRange`] for wider ones.
+    pub fn check_alphabet(&self, bytes: &[u8]) -> Result<(), EncodeError> {
+        match bytes
+            .chunks_exact(self.options.word_size.bytes())
+            .find(|word| self.options.symbol_from_word(word) >= self.params.num_symbols)
+        {
+            Some(word) => Err(self.out_of_alphabet(word)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the error for `word`, whose symbol doesn't fit in the alphabet of the encoder
+    fn out_of_alphabet(&self, word: &[u8]) -> EncodeError {
+        match word {
+            [byte] => EncodeError::NotInAlphabet(*byte),
+            _ => EncodeError::SymbolOutOfRange {
+                symbol: self.options.symbol_from_word(word),
+                num_symbols: self.params.num_symbols,
+            },
+        }
+    }
+
+    /// Encodes `bytes` into `out` without allocating and returns the number of bytes written.
+    /// The written bytes are the encoded bits without a [`Header`] or a length prefix,
+    /// like the ones [`decode_payload`](crate::decoder::decode_payload) takes.
+    /// Returns [`EncodeError::BufferTooSmall`] if the encoded bits don't fit in `out`,
+    /// or the error of [`check_alphabet`](Encoder::check_alphabet) if a word doesn't fit in the alphabet.
+    pub fn encode_into_slice(&self, bytes: &[u8], out: &mut [u8]) -> Result<usize, EncodeError> {
+        let bit_order = self.options.bit_order;
+        let mut accumulator = BitAccumulator::default();
+        let mut written = 0usize;
+        let mut write = |byte: u8| -> Result<(), EncodeError> {
+            let byte = match bit_order {
+                BitOrder::Msb0 => byte,
+                BitOrder::Lsb0 => byte.reverse_bits(),
+            };
+            *out.get_mut(written).ok_or(EncodeError::BufferTooSmall)? = byte;
+            written += 1;
+            Ok(())
+        };
+
+        for word in bytes.chunks_exact(self.options.word_size.bytes()) {
+            let code = self.code_of(self.options.symbol_from_word(word)).ok_or_else(|| self.out_of_alphabet(word))?;
+            accumulator.push(&code);
+            while let Some(byte) = accumulator.pop_byte() {
+                write(byte)?;
+            }
+        }
+
+        let num_unused_bits = num_unused_bits(accumulator.len as usize);
+        if self.require_byte_alignment && num_unused_bits != 0 {
+            return Err(EncodeError::NotByteAligned { padding_bits: num_unused_bits });
+        }
+
+        if let Some(byte) = accumulator.flush() {
+            write(byte)?;
+            let last = &mut out[written - 1];
+            *last = self.padding.apply(*last, num_unused_bits, bit_order);
+        }
+
+        Ok(written)
+    }
+
+    /// Returns the code of `symbol`, from the table of [`compute_encoded_symbols`](Encoder::compute_encoded_symbols)
+    /// if it was computed and computing it otherwise. Returns `None` if `symbol` doesn't fit in the alphabet.
+    fn code_of(&self, symbol: u32) -> Option<EncodedSymbol> {
+        if symbol >= self.params.num_symbols {
+            None
+        } else if self.encoded_symbols.is_empty() {
+            Some(self.encode_symbol(symbol))
+        } else {
+            self.encoded_symbols.get(symbol as usize).cloned()
+        }
+    }
+
+    /// Encodes a single symbol and returns an [`EncodedSymbol`]
+    /// Which holds the encoded symbol as well as the number of bits used to encode it
+    fn encode_symbol(&self, symbol: u32) -> EncodedSymbol {
+        let symbol = self.params.rank(symbol);
+        debug_assert!(symbol < self.params.num_symbols, "only symbols of the alphabet have a code");
+        let mut mask = !0u32;
+        mask >>= u32::BITS - self.params.m as u32;
+
+        let (encoded_symbol, num_bits_encoded) = match symbol.checked_sub(self.params.P) {
+            Some(distance) => {
+                let mut encoded_symbol = self.params.P + (distance / 2u32);
+                encoded_symbol &= mask;
+                encoded_symbol = (encoded_symbol << 1u32) | (distance & 1u32);
+                (encoded_symbol, self.params.m + 1u8)
+            }
+            // Symbols below P get a short code
+            None => (symbol & mask, self.params.m),
         };
 
         EncodedSymbol::new(encoded_symbol, num_bits_encoded)
     }
 }
 
+/// Returns the symbols that encode `count` as a length prefix using an alphabet of `num_symbols` symbols.
+/// The number of base `num_symbols` digits of `count` is written first in unary, as one less 1s followed by a 0,
+/// and the digits themselves follow, most significant first.
+/// Returns `None` if there are less than 2 symbols, since the count can't be written using a single digit.
+///
+/// # Example
+///
+/// ```
+/// use phased_in_codes::encoder::length_prefix_symbols;
+///
+/// // 13 is 21 in base 6
+/// assert_eq!(length_prefix_symbols(13, 6), Some(vec![1, 0, 2, 1]));
+/// // A single symbol has no digits to write the count with
+/// assert_eq!(length_prefix_symbols(13, 1), None);
+/// ```
+pub fn length_prefix_symbols(count: u64, num_symbols: u32) -> Option<Vec<u32>> {
+    if num_symbols < 2 {
+        return None;
+    }
+
+    let base = num_symbols as u64;
+    let mut digits = vec![(count % base) as u32];
+    let mut rest = count / base;
+    while rest != 0 {
+        digits.push((rest % base) as u32);
+        rest /= base;
+    }
+
+    let mut symbols = vec![1u32; digits.len() - 1];
+    symbols.push(0);
+    symbols.extend(digits.iter().rev());
+    Some(symbols)
+}
+
+/// Returns the number of bits that are left unused in the last byte of a stream of `num_bits` bits
+fn num_unused_bits(num_bits: usize) -> u8 {
+    ((u8::BITS as usize - num_bits % u8::BITS as usize) % u8::BITS as usize) as u8
+}
+
+/// Returns the parameters of a stream made of symbols encoded using `left` followed by symbols encoded
+/// using `right`. The bits can only be spliced if both of them use the same alphabet.
+pub fn merge_params(left: &PhasedInParams, right: &PhasedInParams) -> Result<PhasedInParams, MergeError> {
+    if left != right {
+        return Err(MergeError::IncompatibleAlphabets);
+    }
+
+    Ok(left.clone())
+}
+
+/// Re-encodes the compressed file `input`, which was encoded using the `from` parameters,
+/// so that it uses the `to` parameters instead and returns the bytes of the new file.
+/// The file is decompressed using [`pipeline::decompress`], so its preset is undone, and the original input
+/// is encoded as it is. The options, the length prefix and the symbol frequencies of the original header are kept.
+/// Returns [`DecodeError::HeaderMismatch`] if the header wasn't encoded using `from`.
+pub fn transcode(input: &[u8], from: PhasedInParams, to: PhasedInParams) -> Result<Vec<u8>, DecodeError> {
+    let header = header::read_header(input)?;
+    if header.num_symbols != from.num_symbols {
+        return Err(DecodeError::HeaderMismatch);
+    }
+
+    let options = header.options;
+    let decoded = pipeline::decompress(input)?;
+
+    let symbols = options.symbols_from_bytes(&decoded);
+    if let Some(&symbol) = symbols.iter().find(|&&symbol| symbol >= to.num_symbols) {
+        return Err(DecodeError::SymbolOutOfRange { symbol, num_symbols: to.num_symbols });
+    }
+
+    let mut encoder = Encoder::new(to)
+        .with_options(options)
+        .with_frequencies(header.frequencies.is_some())
+        .with_length_prefix(header.length_prefix);
+    encoder.compute_encoded_symbols();
+
+    let mut bytes = Vec::new();
+    encoder
+        .encode_bytes(&decoded)
+        .write_to(&mut bytes)
+        .expect("Writing to a Vec can't fail");
+
+    Ok(bytes)
+}
+
+/// Reads the next frame written by [`EncodedStream::write_length_delimited`] from `reader`
+/// and returns the bytes of the compressed file it holds, or `None` if `reader` ended right before a frame.
+/// Returns an error of kind [`UnexpectedEof`](io::ErrorKind::UnexpectedEof) if `reader` ends in the middle
+/// of the length or of the file bytes.
+///
+/// NOTE: The bytes are read as they arrive, so a corrupted length can't make it allocate more than the frame holds.
+pub fn read_length_delimited<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    let mut filled = 0;
+    while filled != len.len() {
+        match reader.read(&mut len[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the frame length is truncated")),
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    let len = u32::from_be_bytes(len) as usize;
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the frame is truncated"));
+    }
+
+    Ok(Some(bytes))
+}
+
+/// Encodes an input that arrives in chunks, writing the encoded bytes as soon as they are complete.
+/// Like [`Encoder::encode_into_slice`], only the encoded bits are written, without a [`Header`] or a length prefix.
+/// The CRC-32 of the input is computed along the way, so the input never has to be buffered.
+pub struct StreamingEncoder<W: Write> {
+    encoder: Encoder,
+    writer: W,
+    accumulator: BitAccumulator,
+    /// The bytes of a word that is split across chunks
+    partial_word: Vec<u8>,
+    hasher: crc32fast::Hasher,
+    num_symbols: u64,
+}
+
+/// What a [`StreamingEncoder`] reports once the whole input is encoded
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct StreamingSummary {
+    /// The CRC-32 of every byte of the input, the same as computing it over the whole input at once
+    pub input_crc: u32,
+    /// The number of encoded symbols
+    pub num_symbols: u64,
+    /// The number of unused bits at the end of the last written byte
+    pub num_unused_bits: u8,
+}
+
+impl<W: Write> StreamingEncoder<W> {
+    /// Creates a StreamingEncoder that encodes using the settings of `encoder` and writes to `writer`
+    pub fn new(encoder: Encoder, writer: W) -> Self {
+        Self {
+            encoder,
+            writer,
+            accumulator: BitAccumulator::default(),
+            partial_word: Vec::new(),
+            hasher: crc32fast::Hasher::new(),
+            num_symbols: 0,
+        }
+    }
+
+    /// Encodes the next chunk of the input. Chunks don't have to be made of whole words.
+    /// Returns an error of kind [`InvalidInput`](io::ErrorKind::InvalidInput) if a word doesn't fit in the alphabet.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.hasher.update(bytes);
+
+        let word_bytes = self.encoder.options.word_size.bytes();
+        let mut encoded = Vec::with_capacity(bytes.len());
+        let mut bytes = bytes;
+        if !self.partial_word.is_empty() {
+            let missing = (word_bytes - self.partial_word.len()).min(bytes.len());
+            self.partial_word.extend_from_slice(&bytes[..missing]);
+            bytes = &bytes[missing..];
+            if self.partial_word.len() < word_bytes {
+                return Ok(());
+            }
+
+            let word = std::mem::take(&mut self.partial_word);
+            self.push_word(&word, &mut encoded)?;
+        }
+
+        let mut words = bytes.chunks_exact(word_bytes);
+        for word in &mut words {
+            self.push_word(word, &mut encoded)?;
+        }
+        self.partial_word.extend_from_slice(words.remainder());
+
+        self.writer.write_all(&encoded)
+    }
+
+    /// Pads and writes the last encoded byte and returns the [`StreamingSummary`] of the input.
+    /// NOTE: Trailing bytes that don't make up a whole word aren't encoded, but they are part of the CRC.
+    pub fn finish(mut self) -> io::Result<StreamingSummary> {
+        let num_unused_bits = num_unused_bits(self.accumulator.len as usize);
+        if let Some(byte) = self.accumulator.flush() {
+            let byte = self.encoder.padding.apply(byte, num_unused_bits, BitOrder::Msb0);
+            let byte = self.ordered(byte);
+            self.writer.write_all(&[byte])?;
+        }
+        self.writer.flush()?;
+
+        Ok(StreamingSummary {
+            input_crc: self.hasher.finalize(),
+            num_symbols: self.num_symbols,
+            num_unused_bits,
+        })
+    }
+
+    /// Encodes a single word and appends the bytes it completes to `encoded`
+    fn push_word(&mut self, word: &[u8], encoded: &mut Vec<u8>) -> Result<(), EncodeError> {
+        let symbol = self.encoder.options.symbol_from_word(word);
+        let code = self.encoder.code_of(symbol).ok_or_else(|| self.encoder.out_of_alphabet(word))?;
+        self.accumulator.push(&code);
+        self.num_symbols += 1;
+        while let Some(byte) = self.accumulator.pop_byte() {
+            encoded.push(self.ordered(byte));
+        }
+
+        Ok(())
+    }
+
+    /// Packs the bits of `byte`, which is in Msb0 order, using the bit order of the encoder
+    fn ordered(&self, byte: u8) -> u8 {
+        match self.encoder.options.bit_order {
+            BitOrder::Msb0 => byte,
+            BitOrder::Lsb0 => byte.reverse_bits(),
+        }
+    }
+}
+
+/// How the encoder finds the code of every symbol
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum TableStrategy {
+    /// The codes of all the symbols are computed up front by [`Encoder::compute_encoded_symbols`]
+    /// and the input is encoded at once by [`Encoder::encode_bytes`]
+    Precomputed,
+    /// Every code is computed when it's needed, as [`PhasedInEncode`] does
+    OnTheFly,
+}
+
+/// The memory, in bytes, an encode job is expected to need at its peak
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct MemoryEstimate {
+    /// The table with the code of every symbol
+    pub table_bytes: usize,
+    /// The buffer that holds the input
+    pub input_bytes: usize,
+    /// The symbols and codes that are kept while the output is assembled
+    pub intermediate_bytes: usize,
+    /// The encoded bits, assuming every symbol gets a long code
+    pub output_bytes: usize,
+}
+
+impl MemoryEstimate {
+    /// Returns the total number of bytes
+    pub fn peak_bytes(&self) -> usize {
+        self.table_bytes + self.input_bytes + self.intermediate_bytes + self.output_bytes
+    }
+}
+
+/// Estimates the memory needed to encode an input of `input_len` bytes using `params` and `strategy`.
+/// Decoding needs about the same memory, with the input and output switched.
+/// NOTE: The input is assumed to be made of 8-bit words, which overestimates the output of wider words.
+pub fn estimate_memory(input_len: usize, params: &PhasedInParams, strategy: TableStrategy) -> MemoryEstimate {
+    let (_, max_bits) = params.bits_bounds(input_len);
+    let output_bytes = max_bits.div_ceil(u8::BITS as usize);
+
+    match strategy {
+        TableStrategy::Precomputed => MemoryEstimate {
+            table_bytes: params.num_symbols as usize * std::mem::size_of::<EncodedSymbol>(),
+            input_bytes: input_len,
+            intermediate_bytes: input_len * (std::mem::size_of::<u32>() + std::mem::size_of::<EncodedSymbol>()),
+            output_bytes,
+        },
+        TableStrategy::OnTheFly => MemoryEstimate {
+            table_bytes: 0,
+            input_bytes: input_len,
+            intermediate_bytes: 0,
+            output_bytes,
+        },
+    }
+}
+
+/// Accumulates the bits of [`EncodedSymbol`]s and hands out bytes as soon as they are complete
+#[derive(Debug, Default)]
+struct BitAccumulator {
+    bits: u64,
+    len: u8,
+}
+
+impl BitAccumulator {
+    /// Appends the bits of `symbol` after the ones already accumulated.
+    /// NOTE: Complete bytes must be popped before pushing again, so there's always room for a symbol.
+    fn push(&mut self, symbol: &EncodedSymbol) {
+        debug_assert!(self.len < u8::BITS as u8);
+        let mask = (1u64 << symbol.num_bits_encoded) - 1;
+        self.bits = (self.bits << symbol.num_bits_encoded) | (symbol.symbol as u64 & mask);
+        self.len += symbol.num_bits_encoded;
+    }
+
+    /// Removes and returns the oldest complete byte, if there is one
+    fn pop_byte(&mut self) -> Option<u8> {
+        if self.len < u8::BITS as u8 {
+            return None;
+        }
+
+        self.len -= u8::BITS as u8;
+        let byte = (self.bits >> self.len) as u8;
+        self.bits &= (1u64 << self.len) - 1;
+        Some(byte)
+    }
+
+    /// Returns the remaining bits padded with zeros to a whole byte, if there are any
+    fn flush(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let byte = (self.bits << (u8::BITS as u8 - self.len)) as u8;
+        self.bits = 0;
+        self.len = 0;
+        Some(byte)
+    }
+}
+
+/// An iterator that phased-in encodes the symbols of another iterator and yields the encoded bytes.
+/// The last byte is padded with zeros, the same way the bytes of an [`EncodedStream`] are.
+///
+/// This is created by [`PhasedInEncodeExt::phased_in_encode`].
+pub struct PhasedInEncode<I> {
+    symbols: I,
+    encoder: Encoder,
+    accumulator: BitAccumulator,
+}
+
+impl<I> Iterator for PhasedInEncode<I>
+where
+    I: Iterator,
+    I::Item: Borrow<u8>,
+{
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        loop {
+            if let Some(byte) = self.accumulator.pop_byte() {
+                return Some(byte);
+            }
+
+            match self.symbols.next() {
+                Some(symbol) => {
+                    let symbol = *symbol.borrow() as u32;
+                    let num_symbols = self.encoder.params.num_symbols;
+                    assert!(symbol < num_symbols, "Symbol {} doesn't fit in an alphabet of {} symbols", symbol, num_symbols);
+                    let encoded = self.encoder.encode_symbol(symbol);
+                    self.accumulator.push(&encoded);
+                }
+                None => return self.accumulator.flush(),
+            }
+        }
+    }
+}
+
+/// Adds [`phased_in_encode`](PhasedInEncodeExt::phased_in_encode) to every iterator of symbols
+pub trait PhasedInEncodeExt: Iterator + Sized
+where
+    Self::Item: Borrow<u8>,
+{
+    /// Wraps this iterator into one that yields the phased-in encoded bytes of its symbols
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use phased_in_codes::prelude::*;
+    ///
+    /// let symbols: &[u8] = &[0, 1, 2, 3, 4, 5];
+    /// let encoded: Vec<u8> = symbols.iter().phased_in_encode(&PhasedInParams::new(6)).collect();
+    /// assert_eq!(encoded, vec![0b0001_1001, 0b0111_0111]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// The returned iterator panics when it reaches a symbol that doesn't fit in the alphabet of `params`,
+    /// instead of yielding bytes that would decode to another symbol.
+    fn phased_in_encode(self, params: &PhasedInParams) -> PhasedInEncode<Self> {
+        PhasedInEncode {
+            symbols: self,
+            encoder: Encoder::new(params.clone()),
+            accumulator: BitAccumulator::default(),
+        }
+    }
+}
+
+impl<I> PhasedInEncodeExt for I
+where
+    I: Iterator,
+    I::Item: Borrow<u8>,
+{
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::{
+        pipeline::{
+            self,
+            CompressionSettings,
+        },
+        transforms::{
+            Transform,
+            FIRST_CUSTOM_ID,
+        },
+    };
+
     #[test]
     fn encode_symbol_works() {
         let encoder = Encoder::new(PhasedInParams::new(9));
@@ -197,7 +1168,7 @@ mod tests {
 
         let encoded_stream = encoder.encode_bytes(&bytes);
         let expected_stream = EncodedStream::new(Vec::from(expected_symbols));
-        assert_eq!(encoded_stream, expected_stream);
+        assert_eq!(encoded_stream.bits(), expected_stream.bits());
     }
 
     #[test]
@@ -213,6 +1184,537 @@ mod tests {
 
         let encoded_stream = encoder.encode_bytes(&bytes);
         let expected_stream = EncodedStream::new(Vec::from(expected_symbols));
-        assert_eq!(encoded_stream, expected_stream);
+        assert_eq!(encoded_stream.bits(), expected_stream.bits());
+    }
+
+    #[test]
+    fn encoded_stream_copies_the_bits_of_every_symbol() {
+        let params = PhasedInParams::new(70000);
+        let encoder = Encoder::new(params);
+        let symbols: Vec<_> = [0u32, 1, 5535, 5536, 69999, 42].iter().map(|s| encoder.encode_symbol(*s)).collect();
+
+        let expected: String = symbols
+            .iter()
+            .map(|s| format!("{:0width$b}", s.symbol, width = s.num_bits_encoded as usize))
+            .collect();
+        let stream = EncodedStream::new(symbols);
+        let actual: String = stream.bits().iter().map(|bit| if *bit { '1' } else { '0' }).collect();
+
+        assert_eq!(actual, expected);
+        assert_eq!(stream.bits().as_slice().len(), expected.len().div_ceil(8));
+        // The bits are allocated once, without growing while the symbols are appended
+        assert_eq!(stream.bits().capacity(), expected.len().div_ceil(8) * 8);
+    }
+
+    #[test]
+    fn encode_bytes_stores_frequencies() {
+        let mut encoder = Encoder::new(PhasedInParams::new(5)).with_frequencies(true);
+        encoder.compute_encoded_symbols();
+        let bytes: &[u8] = &[0, 4, 4, 1, 0, 4, 2, 4];
+
+        let encoded_stream = encoder.encode_bytes(bytes);
+        let frequencies = encoded_stream.header().frequencies.clone();
+        assert_eq!(frequencies, Some(vec![2, 1, 1, 0, 4]));
+
+        let mut header_bytes = Vec::new();
+        encoded_stream.header().write_to(&mut header_bytes).unwrap();
+        let header = crate::header::read_header(&header_bytes).unwrap();
+        assert_eq!(header.frequencies, frequencies);
+    }
+
+    #[test]
+    fn padding_fills_the_unused_bits() {
+        let bytes: &[u8] = &[0, 1, 2, 3, 4];
+        let params = PhasedInParams::new(5);
+
+        let mut encoder = Encoder::new(params.clone()).with_padding(Padding::Ones);
+        encoder.compute_encoded_symbols();
+        let mut file = Vec::new();
+        encoder.encode_bytes(bytes).write_to(&mut file).unwrap();
+        assert_eq!(file.last(), Some(&0b0111_1111));
+        assert_eq!(Decoder::new(params.clone()).decode_bytes(&file).unwrap(), bytes);
+
+        let options = StreamOptions {
+            bit_order: BitOrder::Lsb0,
+            ..StreamOptions::default()
+        };
+        let mut encoder = Encoder::new(params.clone())
+            .with_options(options)
+            .with_padding(Padding::Pattern(0b1010_0000));
+        encoder.compute_encoded_symbols();
+        let mut file = Vec::new();
+        encoder.encode_bytes(bytes).write_to(&mut file).unwrap();
+        assert_eq!(file.last(), Some(&0b1010_1110));
+        assert_eq!(Decoder::new(params).with_options(options).decode_bytes(&file).unwrap(), bytes);
+    }
+
+    #[test]
+    fn append_compatible_streams_works() {
+        let params = PhasedInParams::new(5);
+        let mut encoder = Encoder::new(params.clone()).with_frequencies(true);
+        encoder.compute_encoded_symbols();
+
+        let mut merged = encoder.encode_bytes(&[0, 1, 2, 3, 4]);
+        merged.append(&encoder.encode_bytes(&[4, 4, 0])).unwrap();
+        assert_eq!(merged.header().frequencies, Some(vec![2, 1, 1, 1, 3]));
+
+        let mut file = Vec::new();
+        merged.write_to(&mut file).unwrap();
+        assert_eq!(Decoder::new(params).decode_bytes(&file).unwrap(), vec![0, 1, 2, 3, 4, 4, 4, 0]);
+    }
+
+    #[test]
+    fn append_rebuilds_the_sparse_index() {
+        let params = PhasedInParams::new(9);
+        let left: Vec<u8> = (0..10).map(|i| i % 9).collect();
+        let right: Vec<u8> = (0..13).map(|i| (i * 4) % 9).collect();
+        let input: Vec<u8> = left.iter().chain(&right).copied().collect();
+        let mut encoder = Encoder::new(params).with_index_interval(Some(4));
+        encoder.compute_encoded_symbols();
+
+        let mut merged = encoder.encode_bytes(&left);
+        merged.append(&encoder.encode_bytes(&right)).unwrap();
+        let index = merged.header().index.clone().unwrap();
+        assert_eq!(index.bit_offsets.len(), 5);
+        assert_eq!(Some(index), encoder.encode_bytes(&input).header().index.clone());
+
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        merged.write_to_file(temp_file.path()).unwrap();
+        assert_eq!(pipeline::decompress_range(temp_file.path(), 13, 21).unwrap(), &input[13..21]);
+    }
+
+    #[test]
+    fn append_incompatible_streams_errors() {
+        let mut left = Encoder::new(PhasedInParams::new(5));
+        left.compute_encoded_symbols();
+        let mut right = Encoder::new(PhasedInParams::new(6));
+        right.compute_encoded_symbols();
+
+        let mut merged = left.encode_bytes(&[0, 1]);
+        assert_eq!(merged.append(&right.encode_bytes(&[5])), Err(MergeError::IncompatibleAlphabets));
+        assert_eq!(
+            merge_params(&PhasedInParams::new(5), &PhasedInParams::new(6)),
+            Err(MergeError::IncompatibleAlphabets)
+        );
+        assert_eq!(merge_params(&PhasedInParams::new(5), &PhasedInParams::new(5)), Ok(PhasedInParams::new(5)));
+    }
+
+    #[test]
+    fn length_prefix_needs_two_symbols() {
+        assert_eq!(length_prefix_symbols(5, 0), None);
+        assert_eq!(length_prefix_symbols(5, 1), None);
+        assert_eq!(length_prefix_symbols(5, 2), Some(vec![1, 1, 0, 1, 0, 1]));
+
+        let encoder = Encoder::new(PhasedInParams::new(1)).with_length_prefix(true);
+        assert_eq!(encoder.try_encode_bytes(&[0, 0]).unwrap_err(), EncodeError::AlphabetTooSmall { num_symbols: 1 });
+    }
+
+    #[test]
+    fn check_alphabet_finds_the_first_symbol_outside_of_it() {
+        let mut encoder = Encoder::new(PhasedInParams::new(4));
+        assert_eq!(encoder.check_alphabet(&[0, 1, 2, 3]), Ok(()));
+        assert_eq!(encoder.check_alphabet(&[0, 1, 9, 4]), Err(EncodeError::NotInAlphabet(9)));
+        assert_eq!(encoder.encode_into_slice(&[3, 4], &mut [0; 4]), Err(EncodeError::NotInAlphabet(4)));
+
+        encoder.compute_encoded_symbols();
+        assert_eq!(encoder.try_encode_bytes(&[0, 1, 9]).unwrap_err(), EncodeError::NotInAlphabet(9));
+        assert_eq!(encoder.encode_into_slice(&[3, 4], &mut [0; 4]), Err(EncodeError::NotInAlphabet(4)));
+
+        let mut streaming = StreamingEncoder::new(encoder, Vec::new());
+        streaming.push_bytes(&[0, 1, 2, 3]).unwrap();
+        assert_eq!(streaming.push_bytes(&[200]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn append_transformed_streams_errors() {
+        struct ReverseBytes;
+
+        impl Transform for ReverseBytes {
+            fn id(&self) -> u8 {
+                FIRST_CUSTOM_ID
+            }
+
+            fn forward(&self, bytes: &[u8]) -> Vec<u8> {
+                bytes.iter().rev().copied().collect()
+            }
+
+            fn inverse(&self, bytes: &[u8]) -> Vec<u8> {
+                self.forward(bytes)
+            }
+        }
+
+        let settings = CompressionSettings::new(PhasedInParams::new(6));
+        let transforms: Vec<Box<dyn Transform>> = vec![Box::new(ReverseBytes)];
+        let mut merged = pipeline::compress_with_transforms(&[0, 1, 2], &settings, &transforms).unwrap();
+        let right = pipeline::compress_with_transforms(&[3, 4, 5], &settings, &transforms).unwrap();
+        assert_eq!(merged.append(&right), Err(MergeError::Transformed));
+
+        let mut plain = pipeline::compress(&[0, 1, 2], &settings).unwrap();
+        assert_eq!(plain.append(&right), Err(MergeError::Transformed));
+    }
+
+    #[test]
+    fn length_prefix_round_trips() {
+        for num_symbols in [2u32, 3, 6, 256].iter() {
+            let params = PhasedInParams::new(*num_symbols);
+            let bytes: Vec<u8> = (0..300u32).map(|i| (i * 7 % num_symbols) as u8).collect();
+
+            let mut encoder = Encoder::new(params.clone()).with_length_prefix(true);
+            encoder.compute_encoded_symbols();
+            let encoded_stream = encoder.encode_bytes(&bytes);
+            assert!(encoded_stream.header().length_prefix);
+
+            let prefix = length_prefix_symbols(bytes.len() as u64, *num_symbols).unwrap();
+            let prefix_bits: usize = prefix.iter().map(|s| encoder.encode_symbol(*s).num_bits_encoded as usize).sum();
+            let (_, max_bits) = params.bits_bounds(bytes.len());
+            assert!(encoded_stream.bits().len() <= prefix_bits + max_bits);
+
+            let mut file = Vec::new();
+            encoded_stream.with_padding(Padding::Ones).write_to(&mut file).unwrap();
+            assert_eq!(Decoder::new(params).decode_bytes(&file).unwrap(), bytes, "{} symbols", num_symbols);
+        }
+    }
+
+    #[test]
+    fn encode_symbol_picks_the_code_length_at_the_boundary() {
+        for num_symbols in [3u32, 5, 6, 9, 100, 1000, 70000].iter() {
+            let params = PhasedInParams::new(*num_symbols);
+            let encoder = Encoder::new(params.clone());
+
+            let short = encoder.encode_symbol(params.P - 1);
+            assert_eq!(short, EncodedSymbol::new(params.P - 1, params.m), "{} symbols", num_symbols);
+
+            let long = encoder.encode_symbol(params.P);
+            assert_eq!(long, EncodedSymbol::new(params.P << 1, params.m + 1), "{} symbols", num_symbols);
+        }
+    }
+
+    #[test]
+    fn reverse_order_round_trips() {
+        let bytes: Vec<u8> = (0..9).chain(0..9).collect();
+        let params = PhasedInParams::new(9).with_reverse(true);
+        let mut encoder = Encoder::new(params.clone()).with_length_prefix(true);
+        encoder.compute_encoded_symbols();
+        assert_eq!(encoder.encode_symbol(8), EncodedSymbol::new(0, 3));
+        assert_eq!(encoder.encode_symbol(0), EncodedSymbol::new(0b1111, 4));
+
+        let file = encoder.encode_bytes(&bytes).to_contiguous_file_bytes();
+        let stream = EncodedStream::from_encoded_bytes(&file).unwrap();
+        assert!(stream.header().reverse);
+        assert_eq!(Decoder::from_header(stream.header()).decode_stream(&stream).unwrap(), bytes);
+        assert_eq!(Decoder::new(PhasedInParams::new(9)).decode_bytes(&file), Err(DecodeError::HeaderMismatch));
+    }
+
+    #[test]
+    fn reverse_order_favours_high_symbols() {
+        // Mostly the two highest symbols, which get long codes in the usual order
+        let bytes: Vec<u8> = (0..1000).map(|i| if i % 10 == 0 { i as u8 % 12 } else { 12 + i as u8 % 2 }).collect();
+        let params = PhasedInParams::new(14);
+
+        let usual = Encoder::new(params.clone()).encode_bytes(&bytes).bits().len();
+        let reversed = Encoder::new(params.with_reverse(true)).encode_bytes(&bytes).bits().len();
+        assert!(reversed * 5 < usual * 4, "{} bits reversed, {} bits usually", reversed, usual);
+    }
+
+    #[test]
+    fn adversarial_payload_lengths_are_rejected() {
+        let bytes: Vec<u8> = (0..20).map(|i| i % 9).collect();
+        let file = Encoder::new(PhasedInParams::new(9)).encode_bytes(&bytes).to_contiguous_file_bytes();
+        let header_len = file.len() - 8;
+
+        // Byte 7 of the header holds the number of unused bits
+        let mut empty_payload = file[..header_len].to_vec();
+        empty_payload[7] = 4;
+        assert_eq!(EncodedStream::from_encoded_bytes(&empty_payload).unwrap_err(), DecodeError::InvalidUnusedBits(4));
+
+        let mut too_many_unused = file.clone();
+        too_many_unused[7] = 9;
+        assert_eq!(EncodedStream::from_encoded_bytes(&too_many_unused).unwrap_err(), DecodeError::InvalidUnusedBits(9));
+
+        // Every truncation either fails cleanly or yields fewer bits
+        for len in 0..file.len() {
+            if let Ok(stream) = EncodedStream::from_encoded_bytes(&file[..len]) {
+                assert!(stream.bits().len() < 20 * 4);
+            }
+        }
+    }
+
+    #[test]
+    fn every_code_decodes_to_its_symbol() {
+        for num_symbols in 3..=16 {
+            for reverse in [false, true].iter() {
+                let params = PhasedInParams::new(num_symbols).with_reverse(*reverse);
+                let encoder = Encoder::new(params.clone());
+                let decoder = Decoder::new(params.clone());
+
+                for symbol in 0..num_symbols {
+                    let code = encoder.encode_symbol(symbol);
+                    let rank = params.rank(symbol);
+                    if rank >= params.P {
+                        assert_eq!(code.num_bits_encoded, params.m + 1);
+                        assert_eq!(code.symbol & 1, (rank - params.P) & 1, "{} of {}", symbol, num_symbols);
+                    } else {
+                        assert_eq!(code.num_bits_encoded, params.m);
+                    }
+
+                    let stream = EncodedStream::from_codes(&[(code.symbol, code.num_bits_encoded)]);
+                    let decoded = decoder.decode_stream(&stream).unwrap();
+                    assert_eq!(decoded, vec![symbol as u8], "{} of {}, reverse: {}", symbol, num_symbols, reverse);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn verify_decodes_to_works() {
+        let bytes: Vec<u8> = (0..9).chain(0..9).collect();
+        let params = PhasedInParams::new(9);
+        let mut encoder = Encoder::new(params.clone());
+        encoder.compute_encoded_symbols();
+
+        let encoded_stream = encoder.encode_bytes(&bytes);
+        assert!(encoded_stream.verify_decodes_to(&bytes));
+        assert!(!encoded_stream.verify_decodes_to(&bytes[1..]));
+
+        let mut file = Vec::new();
+        encoded_stream.write_to(&mut file).unwrap();
+        let truncated = EncodedStream::from_encoded_bytes(&file[..file.len() - 2]).unwrap();
+        assert!(!truncated.verify_decodes_to(&bytes));
+
+        let bytes: Vec<u8> = (0..300).map(|i| ((i / 20) % 9) as u8).collect();
+        for level in [0, 3, 5, pipeline::MAX_LEVEL].iter() {
+            let settings = CompressionSettings {
+                level: *level,
+                ..CompressionSettings::new(params.clone())
+            };
+            let stream = pipeline::compress(&bytes, &settings).unwrap();
+            assert!(stream.verify_decodes_to(&bytes), "level {}", level);
+            assert!(!stream.verify_decodes_to(&bytes[1..]), "level {}", level);
+        }
+    }
+
+    #[test]
+    fn payload_checksum_catches_corruption() {
+        let bytes: Vec<u8> = (0..100).map(|i| i % 9).collect();
+        let params = PhasedInParams::new(9);
+        let mut encoder = Encoder::new(params.clone());
+        encoder.compute_encoded_symbols();
+
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let path = temp_file.path();
+        encoder.encode_bytes(&bytes).with_payload_checksum(true).write_to_file(path).unwrap();
+        let stream = EncodedStream::read_from_file(path).unwrap();
+        assert!(stream.header().payload_crc.is_some());
+        assert!(stream.verify_decodes_to(&bytes));
+
+        let mut file = fs::read(path).unwrap();
+        let last = file.len() - 10;
+        file[last] ^= 0b0001_0000;
+        fs::write(path, &file).unwrap();
+
+        let error = EncodedStream::read_from_file(path).unwrap_err();
+        assert!(matches!(error, DecodeError::PayloadChecksumMismatch { .. }), "{:?}", error);
+        assert_eq!(crate::pipeline::decompress(&file), Err(error));
+    }
+
+    #[test]
+    fn estimate_memory_bounds_the_actual_usage() {
+        let params = PhasedInParams::new(9);
+        let bytes: Vec<u8> = (0..1000u32).map(|i| (i * 5 % 9) as u8).collect();
+
+        let mut encoder = Encoder::new(params.clone());
+        encoder.compute_encoded_symbols();
+        let output_len = encoder.encode_bytes(&bytes).bits().as_slice().len();
+
+        let estimate = estimate_memory(bytes.len(), &params, TableStrategy::Precomputed);
+        assert_eq!(estimate.table_bytes, encoder.encoded_symbols.len() * std::mem::size_of::<EncodedSymbol>());
+        assert_eq!(estimate.input_bytes, bytes.len());
+        assert!(estimate.output_bytes >= output_len && estimate.output_bytes <= output_len * 4 / 3 + 1);
+
+        let streamed_len = bytes.iter().phased_in_encode(&params).count();
+        let estimate = estimate_memory(bytes.len(), &params, TableStrategy::OnTheFly);
+        assert_eq!(estimate.table_bytes, 0);
+        assert!(estimate.output_bytes >= streamed_len && estimate.output_bytes <= streamed_len * 4 / 3 + 1);
+        assert!(estimate.peak_bytes() < estimate_memory(bytes.len(), &params, TableStrategy::Precomputed).peak_bytes());
+    }
+
+    #[test]
+    fn encode_into_slice_fills_an_exact_buffer() {
+        let bytes: Vec<u8> = (0..9).collect();
+        let mut encoder = Encoder::new(PhasedInParams::new(9)).with_padding(Padding::Ones);
+        encoder.compute_encoded_symbols();
+
+        let mut expected = Vec::new();
+        encoder.encode_bytes(&bytes).write_to(&mut expected).unwrap();
+        let expected = &expected[expected.len() - 4..];
+
+        let mut out = [0u8; 4];
+        assert_eq!(encoder.encode_into_slice(&bytes, &mut out), Ok(4));
+        assert_eq!(&out, expected);
+    }
+
+    #[test]
+    fn encode_into_slice_rejects_a_small_buffer() {
+        let bytes: Vec<u8> = (0..9).collect();
+        let encoder = Encoder::new(PhasedInParams::new(9));
+
+        let mut out = [0u8; 3];
+        assert_eq!(encoder.encode_into_slice(&bytes, &mut out), Err(EncodeError::BufferTooSmall));
+        assert_eq!(encoder.encode_into_slice(&bytes, &mut []), Err(EncodeError::BufferTooSmall));
+    }
+
+    #[test]
+    fn required_byte_alignment_rejects_padding() {
+        let encoder = Encoder::new(PhasedInParams::new(9)).with_byte_alignment_required(true);
+
+        // 7 short codes and 2 long codes take 29 bits
+        let bytes: Vec<u8> = (0..9).collect();
+        let expected = EncodeError::NotByteAligned { padding_bits: 3 };
+        assert_eq!(encoder.try_encode_bytes(&bytes).unwrap_err(), expected);
+        assert_eq!(encoder.encode_into_slice(&bytes, &mut [0u8; 4]), Err(expected));
+    }
+
+    #[test]
+    fn required_byte_alignment_accepts_aligned_codes() {
+        let encoder = Encoder::new(PhasedInParams::new(9)).with_byte_alignment_required(true);
+
+        // 8 short codes take 24 bits
+        let bytes: Vec<u8> = (0..8).map(|i| i % 7).collect();
+        let stream = encoder.try_encode_bytes(&bytes).unwrap();
+        assert_eq!(stream.bits().len(), 24);
+        assert_eq!(encoder.encode_into_slice(&bytes, &mut [0u8; 3]), Ok(3));
+    }
+
+    #[test]
+    fn streaming_crc_matches_the_one_shot_crc() {
+        let data: Vec<u8> = (0..1001u32).map(|i| (i * 31 % 250) as u8).collect();
+        let options = StreamOptions {
+            word_size: WordSize::Bits16,
+            ..StreamOptions::default()
+        };
+        let encoder = || Encoder::new(PhasedInParams::new(65535)).with_options(options);
+
+        let mut expected = vec![0u8; 2000];
+        let expected_len = encoder().encode_into_slice(&data, &mut expected).unwrap();
+        expected.truncate(expected_len);
+
+        for chunk_size in [1, 3, 64, 1000, 4096].iter() {
+            let mut encoded = Vec::new();
+            let mut streaming = StreamingEncoder::new(encoder(), &mut encoded);
+            data.chunks(*chunk_size).for_each(|chunk| streaming.push_bytes(chunk).unwrap());
+            let summary = streaming.finish().unwrap();
+
+            assert_eq!(summary.input_crc, crc32fast::hash(&data), "chunks of {}", chunk_size);
+            assert_eq!(summary.num_symbols, 500);
+            assert_eq!(encoded, expected, "chunks of {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn contiguous_file_bytes_match_the_written_file() {
+        let bytes: Vec<u8> = (0..50).map(|i| i % 7).collect();
+        let options = StreamOptions {
+            bit_order: BitOrder::Lsb0,
+            ..StreamOptions::default()
+        };
+        let mut encoder = Encoder::new(PhasedInParams::new(7)).with_options(options).with_frequencies(true);
+        encoder.compute_encoded_symbols();
+        let encoded_stream = encoder.encode_bytes(&bytes).with_payload_checksum(true);
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        encoded_stream.write_to_file(file.path()).unwrap();
+        assert_eq!(encoded_stream.to_contiguous_file_bytes(), fs::read(file.path()).unwrap());
+    }
+
+    #[test]
+    fn transcode_works() {
+        let bytes: Vec<_> = (0..9).chain((0..9).rev()).collect();
+        let mut encoder = Encoder::new(PhasedInParams::new(9));
+        encoder.compute_encoded_symbols();
+        let mut input = Vec::new();
+        encoder.encode_bytes(&bytes).write_to(&mut input).unwrap();
+
+        let transcoded = transcode(&input, PhasedInParams::new(9), PhasedInParams::new(16)).unwrap();
+        let decoded = Decoder::new(PhasedInParams::new(16)).decode_bytes(&transcoded).unwrap();
+        assert_eq!(decoded, bytes);
+
+        let error = transcode(&input, PhasedInParams::new(9), PhasedInParams::new(4));
+        assert_eq!(error, Err(DecodeError::SymbolOutOfRange { symbol: 4, num_symbols: 4 }));
+        let error = transcode(&input, PhasedInParams::new(10), PhasedInParams::new(16));
+        assert_eq!(error, Err(DecodeError::HeaderMismatch));
+    }
+
+    #[test]
+    fn transcode_undoes_the_pipeline() {
+        let bytes: Vec<u8> = (0..300).map(|i| [3, 10, 200][i % 3]).collect();
+        let transformed = CompressionSettings {
+            level: pipeline::MAX_LEVEL,
+            ..CompressionSettings::new(PhasedInParams::new(201))
+        };
+        let densified = CompressionSettings {
+            densify: true,
+            channels: 2,
+            ..CompressionSettings::new(PhasedInParams::new(201))
+        };
+
+        for settings in [transformed, densified].iter() {
+            let input = pipeline::compress(&bytes, settings).unwrap().to_contiguous_file_bytes();
+            let header = header::read_header(&input).unwrap();
+
+            let transcoded = transcode(&input, PhasedInParams::new(header.num_symbols), PhasedInParams::new(256)).unwrap();
+            assert_eq!(pipeline::decompress(&transcoded).unwrap(), bytes);
+            assert_eq!(Decoder::new(PhasedInParams::new(256)).decode_bytes(&transcoded).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn phased_in_encode_matches_encode_bytes() {
+        for num_symbols in [3u32, 6, 9, 16, 200] {
+            let params = PhasedInParams::new(num_symbols);
+            let bytes: Vec<u8> = (0..1000u32).map(|i| ((i * 7 + i / 3) % num_symbols) as u8).collect();
+
+            let mut encoder = Encoder::new(params.clone());
+            encoder.compute_encoded_symbols();
+            let mut file = Vec::new();
+            encoder.encode_bytes(&bytes).write_to(&mut file).unwrap();
+            let (_, header_len) = Header::read(&file).unwrap();
+
+            for len in [0, 1, 2, 5, 999, 1000] {
+                let mut expected = Vec::new();
+                encoder.encode_bytes(&bytes[..len]).write_to(&mut expected).unwrap();
+                let encoded: Vec<u8> = bytes[..len].iter().phased_in_encode(&params).collect();
+                assert_eq!(encoded, &expected[header_len..], "{} symbols, {} bytes", num_symbols, len);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Symbol 9 doesn't fit in an alphabet of 4 symbols")]
+    fn phased_in_encode_panics_on_symbols_outside_of_the_alphabet() {
+        let _: Vec<u8> = [0u8, 9].iter().phased_in_encode(&PhasedInParams::new(4)).collect();
+    }
+
+    #[test]
+    fn length_delimited_frames_round_trip() {
+        let mut encoder = Encoder::new(PhasedInParams::new(5));
+        encoder.compute_encoded_symbols();
+        let first = encoder.encode_bytes(&[0, 1, 2, 3, 4, 4, 4]);
+        let second = encoder.encode_bytes(&[]);
+
+        let mut buffer = Vec::new();
+        first.write_length_delimited(&mut buffer).unwrap();
+        second.write_length_delimited(&mut buffer).unwrap();
+        assert_eq!(buffer[..4], (first.to_contiguous_file_bytes().len() as u32).to_be_bytes());
+
+        let mut reader = io::BufReader::new(buffer.as_slice());
+        assert_eq!(read_length_delimited(&mut reader).unwrap(), Some(first.to_contiguous_file_bytes()));
+        assert_eq!(read_length_delimited(&mut reader).unwrap(), Some(second.to_contiguous_file_bytes()));
+        assert_eq!(read_length_delimited(&mut reader).unwrap(), None);
+
+        let first_frame_len = 4 + first.to_contiguous_file_bytes().len();
+        for len in [2, 4, first_frame_len - 1] {
+            let error = read_length_delimited(&mut &buffer[..len]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof, "{} bytes", len);
+        }
     }
 }
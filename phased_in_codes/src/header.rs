@@ -0,0 +1,584 @@
+//! Contains the [`Header`] that is written in front of every encoded stream
+
+use crate::{
+    common::{
+        BitOrder,
+        PhasedInParams,
+        StreamOptions,
+        WordSize,
+    },
+    decoder::DecodeError,
+    pipeline::Preset,
+};
+use std::{
+    convert::TryFrom,
+    io::{
+        self,
+        Write,
+    },
+};
+
+/// The bytes every compressed file starts with
+pub const MAGIC: [u8; 4] = *b"PHIN";
+
+/// The bytes every file written by [`pack`](crate::pack::pack) starts with, in front of its shared [`Header`],
+/// so that a pack is never mistaken for a single compressed stream
+pub const PACK_MAGIC: [u8; 4] = *b"PHPK";
+
+/// The version of the file format described by [`Header`]
+pub const VERSION: u8 = 1;
+
+/// Set when the header contains the frequency of each symbol
+const FLAG_FREQUENCIES: u16 = 1 << 0;
+/// Set when the encoded bits are packed into bytes using [`BitOrder::Lsb0`]
+const FLAG_LSB0: u16 = 1 << 1;
+/// Set when the header contains a symbol map
+const FLAG_SYMBOL_MAP: u16 = 1 << 2;
+/// Set when the payload starts with the number of encoded symbols
+const FLAG_LENGTH_PREFIX: u16 = 1 << 3;
+/// Set when the header contains a dense map
+const FLAG_DENSE_MAP: u16 = 1 << 4;
+/// Set when the header contains a [`SparseIndex`]
+const FLAG_INDEX: u16 = 1 << 5;
+/// Set when the header contains the checksum of the payload
+const FLAG_PAYLOAD_CRC: u16 = 1 << 6;
+/// Set when the header contains the number of channels
+const FLAG_CHANNELS: u16 = 1 << 7;
+/// Set when the symbols were encoded in reverse phased-in order
+const FLAG_REVERSE: u16 = 1 << 8;
+/// Set when the header contains the ids of the transforms applied to the input
+const FLAG_TRANSFORMS: u16 = 1 << 9;
+
+/// Holds the information that is written in front of an encoded stream.
+///
+/// The header has the following layout:
+///
+/// Magic:       4 bytes, always [`MAGIC`]
+/// Version:     1 byte, always [`VERSION`]
+/// Flags:       2 bytes (little endian), denote which of the optional sections are present
+/// Unused bits: 1 byte, the number of bits that were not used from the last byte of the stream
+/// Symbols:     4 bytes (little endian), the number of distinct symbols the stream was encoded with
+/// Word size:   1 byte, the number of bits in every word of the input
+/// Offset:      4 bytes (little endian), the value that was subtracted from every word
+/// Preset:      1 byte, the id of the [`Preset`] the file was compressed with
+/// Frequencies: Optional. The number of symbols followed by the frequency of each symbol, all as varints
+/// Symbol map:  Optional. The number of entries as a varint followed by the original byte of each symbol
+/// Dense map:   Optional. The number of entries as a varint followed by the byte each densified byte stands for
+/// Payload CRC: Optional. 4 bytes (little endian), the CRC-32 of the encoded bytes that follow the header
+/// Channels:    Optional. The number of channels the input was deinterleaved into, as a varint
+/// Transforms:  Optional. The number of transforms as a varint followed by their ids, in the order they were applied
+/// Index:       Optional. The interval and the number of points of the [`SparseIndex`], followed by the bit offset
+///              of every point minus the one of the previous point, all as varints
+///
+/// The bit order, whether the payload starts with a length prefix and whether the phased-in order is reversed
+/// are stored in the flags.
+/// Every multi-byte field is little endian, no matter the byte order of the machine that writes or reads it.
+/// This is the only place that knows how the [`StreamOptions`] are laid out in a file.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct Header {
+    pub num_unused_bits: u8,
+    pub num_symbols: u32,
+    pub options: StreamOptions,
+    pub preset: Preset,
+    pub frequencies: Option<Vec<u64>>,
+    pub symbol_map: Option<Vec<u8>>,
+    /// Whether the payload starts with the number of encoded symbols, encoded using phased-in codes as well
+    pub length_prefix: bool,
+    /// Whether the symbols were encoded in reverse phased-in order, see [`PhasedInParams::with_reverse`]
+    pub reverse: bool,
+    /// The map that reverses [`densify`](crate::transforms::densify), if the input was densified before compressing it
+    pub dense_map: Option<Vec<u8>>,
+    /// The CRC-32 of the encoded bytes as they are written after the header, used to catch transport corruption
+    pub payload_crc: Option<u32>,
+    /// The number of channels, if the input was [`deinterleave`](crate::transforms::deinterleave)d before compressing it
+    pub channels: Option<u32>,
+    /// The ids of the [`Transform`](crate::transforms::Transform)s applied to the input in order, empty if there are none
+    pub transforms: Vec<u8>,
+    /// The bit offsets of every `interval`-th symbol of the payload, used to start decoding in the middle of it
+    pub index: Option<SparseIndex>,
+}
+
+/// Points at the codes of every `interval`-th symbol of a payload, so that decoding can start at the
+/// nearest point instead of the start of the payload
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct SparseIndex {
+    /// The number of symbols between two consecutive points
+    pub interval: u64,
+    /// The bit offset in the payload of the code of symbol `(i + 1) * interval`, for every point `i`.
+    /// The offsets include the length prefix of the payload, if there is one, and never decrease.
+    pub bit_offsets: Vec<u64>,
+}
+
+impl SparseIndex {
+    /// Returns the index of the symbol and the bit offset of the nearest point at or before `symbol`,
+    /// or `None` if there is no such point, in which case decoding has to start from the first symbol
+    pub fn seek(&self, symbol: u64) -> Option<(u64, u64)> {
+        let point = symbol.checked_div(self.interval)?.min(self.bit_offsets.len() as u64);
+        let point = point.checked_sub(1)?;
+        Some(((point + 1) * self.interval, self.bit_offsets[point as usize]))
+    }
+}
+
+/// Helper to read the header fields one after the other
+pub(crate) struct HeaderReader<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> HeaderReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, cursor: 0 }
+    }
+
+    pub(crate) fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self.cursor.checked_add(len).ok_or(DecodeError::TruncatedHeader)?;
+        let bytes = self.bytes.get(self.cursor..end).ok_or(DecodeError::TruncatedHeader)?;
+        self.cursor = end;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, DecodeError> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, DecodeError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads a map of bytes, stored as the number of entries followed by the entries
+    fn read_byte_map(&mut self) -> Result<Vec<u8>, DecodeError> {
+        let num_entries = self.read_varint()?;
+        let num_entries = usize::try_from(num_entries).map_err(|_| DecodeError::TruncatedHeader)?;
+        Ok(self.read_bytes(num_entries)?.to_vec())
+    }
+
+    /// Reads an unsigned LEB128 varint
+    pub(crate) fn read_varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8()?;
+            let bits = (byte & 0x7F) as u64;
+            if bits.leading_zeros() < shift {
+                return Err(DecodeError::InvalidVarint);
+            }
+
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(DecodeError::InvalidVarint)
+    }
+}
+
+fn write_u8<W: Write>(writer: &mut W, value: u8) -> io::Result<()> {
+    writer.write_all(&[value])
+}
+
+fn write_u16<W: Write>(writer: &mut W, value: u16) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+/// Writes `value` as an unsigned LEB128 varint
+pub(crate) fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+/// Writes `map` as the number of entries followed by the entries
+fn write_byte_map<W: Write>(writer: &mut W, map: &[u8]) -> io::Result<()> {
+    write_varint(writer, map.len() as u64)?;
+    writer.write_all(map)
+}
+
+impl Header {
+    /// Returns the [`PhasedInParams`] the stream was encoded with
+    pub fn params(&self) -> PhasedInParams {
+        PhasedInParams::new(self.num_symbols).with_reverse(self.reverse)
+    }
+
+    /// Writes the header to `writer` using the layout described in [`Header`]
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut flags = 0u16;
+        if self.frequencies.is_some() {
+            flags |= FLAG_FREQUENCIES;
+        }
+        if self.options.bit_order == BitOrder::Lsb0 {
+            flags |= FLAG_LSB0;
+        }
+        if self.symbol_map.is_some() {
+            flags |= FLAG_SYMBOL_MAP;
+        }
+        if self.length_prefix {
+            flags |= FLAG_LENGTH_PREFIX;
+        }
+        if self.reverse {
+            flags |= FLAG_REVERSE;
+        }
+        if self.dense_map.is_some() {
+            flags |= FLAG_DENSE_MAP;
+        }
+        if self.payload_crc.is_some() {
+            flags |= FLAG_PAYLOAD_CRC;
+        }
+        if self.channels.is_some() {
+            flags |= FLAG_CHANNELS;
+        }
+        if !self.transforms.is_empty() {
+            flags |= FLAG_TRANSFORMS;
+        }
+        if self.index.is_some() {
+            flags |= FLAG_INDEX;
+        }
+
+        writer.write_all(&MAGIC)?;
+        write_u8(writer, VERSION)?;
+        write_u16(writer, flags)?;
+        write_u8(writer, self.num_unused_bits)?;
+        write_u32(writer, self.num_symbols)?;
+        write_u8(writer, self.options.word_size.bits())?;
+        write_u32(writer, self.options.offset)?;
+        write_u8(writer, self.preset.id())?;
+
+        if let Some(frequencies) = &self.frequencies {
+            write_varint(writer, frequencies.len() as u64)?;
+            for frequency in frequencies {
+                write_varint(writer, *frequency)?;
+            }
+        }
+
+        if let Some(symbol_map) = &self.symbol_map {
+            write_byte_map(writer, symbol_map)?;
+        }
+
+        if let Some(dense_map) = &self.dense_map {
+            write_byte_map(writer, dense_map)?;
+        }
+
+        if let Some(payload_crc) = self.payload_crc {
+            write_u32(writer, payload_crc)?;
+        }
+
+        if let Some(channels) = self.channels {
+            write_varint(writer, channels as u64)?;
+        }
+
+        if !self.transforms.is_empty() {
+            write_byte_map(writer, &self.transforms)?;
+        }
+
+        if let Some(index) = &self.index {
+            write_varint(writer, index.interval)?;
+            write_varint(writer, index.bit_offsets.len() as u64)?;
+            let mut previous = 0u64;
+            for offset in &index.bit_offsets {
+                let delta = offset.checked_sub(previous).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "the offsets of the index decrease")
+                })?;
+                write_varint(writer, delta)?;
+                previous = *offset;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a header from the start of `bytes`.
+    /// Returns the header along with the number of bytes it occupies,
+    /// which is where the encoded bytes begin.
+    pub fn read(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let mut reader = HeaderReader::new(bytes);
+
+        match reader.read_bytes(MAGIC.len())? {
+            magic if magic == MAGIC => {}
+            magic if magic == PACK_MAGIC => return Err(DecodeError::PackedFile),
+            _ => return Err(DecodeError::InvalidMagic),
+        }
+
+        let version = reader.read_u8()?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let flags = reader.read_u16()?;
+        let num_unused_bits = reader.read_u8()?;
+        let num_symbols = reader.read_u32()?;
+        if num_symbols == 0 {
+            return Err(DecodeError::EmptyAlphabet);
+        }
+        let word_size = reader.read_u8()?;
+        let word_size = WordSize::from_bits(word_size).ok_or(DecodeError::InvalidWordSize(word_size))?;
+        let offset = reader.read_u32()?;
+        let preset = reader.read_u8()?;
+        let preset = Preset::from_id(preset).ok_or(DecodeError::InvalidPreset(preset))?;
+
+        let bit_order = if flags & FLAG_LSB0 != 0 {
+            BitOrder::Lsb0
+        } else {
+            BitOrder::Msb0
+        };
+
+        let frequencies = if flags & FLAG_FREQUENCIES != 0 {
+            let num_frequencies = reader.read_varint()?;
+            // Every frequency takes at least one byte, so don't trust a length the input can't hold
+            if num_frequencies > (bytes.len() - reader.cursor) as u64 {
+                return Err(DecodeError::TruncatedHeader);
+            }
+
+            let frequencies = (0..num_frequencies)
+                .map(|_| reader.read_varint())
+                .collect::<Result<Vec<_>, _>>()?;
+            Some(frequencies)
+        } else {
+            None
+        };
+
+        let symbol_map = if flags & FLAG_SYMBOL_MAP != 0 {
+            Some(reader.read_byte_map()?)
+        } else {
+            None
+        };
+
+        let dense_map = if flags & FLAG_DENSE_MAP != 0 {
+            Some(reader.read_byte_map()?)
+        } else {
+            None
+        };
+
+        let payload_crc = if flags & FLAG_PAYLOAD_CRC != 0 {
+            Some(reader.read_u32()?)
+        } else {
+            None
+        };
+
+        let channels = if flags & FLAG_CHANNELS != 0 {
+            let channels = reader.read_varint()?;
+            Some(u32::try_from(channels).map_err(|_| DecodeError::InvalidVarint)?)
+        } else {
+            None
+        };
+
+        let transforms = if flags & FLAG_TRANSFORMS != 0 {
+            reader.read_byte_map()?
+        } else {
+            Vec::new()
+        };
+
+        let index = if flags & FLAG_INDEX != 0 {
+            let interval = reader.read_varint()?;
+            let num_points = reader.read_varint()?;
+            // Every offset takes at least one byte, so don't trust a length the input can't hold
+            if num_points > (bytes.len() - reader.cursor) as u64 {
+                return Err(DecodeError::TruncatedHeader);
+            }
+
+            let mut bit_offsets = Vec::with_capacity(num_points as usize);
+            let mut offset = 0u64;
+            for _ in 0..num_points {
+                offset = offset.checked_add(reader.read_varint()?).ok_or(DecodeError::InvalidVarint)?;
+                bit_offsets.push(offset);
+            }
+            Some(SparseIndex { interval, bit_offsets })
+        } else {
+            None
+        };
+
+        let header = Self {
+            num_unused_bits,
+            num_symbols,
+            options: StreamOptions {
+                bit_order,
+                word_size,
+                offset,
+            },
+            preset,
+            frequencies,
+            symbol_map,
+            length_prefix: flags & FLAG_LENGTH_PREFIX != 0,
+            reverse: flags & FLAG_REVERSE != 0,
+            dense_map,
+            payload_crc,
+            channels,
+            transforms,
+            index,
+        };
+
+        Ok((header, reader.cursor))
+    }
+}
+
+/// Reads the [`Header`] of a compressed file without decoding the rest of it
+pub fn read_header(bytes: &[u8]) -> Result<Header, DecodeError> {
+    Header::read(bytes).map(|(header, _)| header)
+}
+
+/// Returns the [`Header`] of `bytes` if they look like a compressed file, that is they start with a whole header
+/// of a supported version that describes a non-empty alphabet. Nothing after the header is decoded or checked.
+/// This never panics, so it's suitable for telling the type of arbitrary files.
+pub fn probe(bytes: &[u8]) -> Option<Header> {
+    read_header(bytes)
+        .ok()
+        .filter(|header| (header.num_unused_bits as u32) < u8::BITS)
+}
+
+/// Returns whether `bytes` look like a compressed file, see [`probe`]
+pub fn is_phased_in_file(bytes: &[u8]) -> bool {
+    probe(bytes).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::PhasedInParams,
+        encoder::{
+            EncodedStream,
+            Encoder,
+        },
+    };
+
+    #[test]
+    fn header_round_trip_works() {
+        let header = Header {
+            num_unused_bits: 5,
+            num_symbols: 70000,
+            options: StreamOptions {
+                bit_order: BitOrder::Lsb0,
+                word_size: WordSize::Bits32,
+                offset: 123456,
+            },
+            preset: Preset::Full,
+            frequencies: Some(vec![0, 1, 127, 128, 300, u64::MAX]),
+            symbol_map: Some(vec![9, 3, 250]),
+            length_prefix: true,
+            reverse: true,
+            dense_map: Some(vec![3, 10, 200]),
+            payload_crc: Some(0xDEAD_BEEF),
+            channels: Some(3),
+            transforms: vec![1, 0, 200],
+            index: Some(SparseIndex {
+                interval: 64,
+                bit_offsets: vec![300, 301, 70000],
+            }),
+        };
+
+        let mut bytes = Vec::new();
+        header.write_to(&mut bytes).unwrap();
+        bytes.extend_from_slice(&[0xAB, 0xCD]);
+
+        let (read, len) = Header::read(&bytes).unwrap();
+        assert_eq!(read, header);
+        assert_eq!(&bytes[len..], &[0xAB, 0xCD]);
+    }
+
+    #[test]
+    fn sparse_index_seeks_to_the_nearest_point() {
+        let index = SparseIndex {
+            interval: 10,
+            bit_offsets: vec![35, 72],
+        };
+        assert_eq!(index.seek(0), None);
+        assert_eq!(index.seek(9), None);
+        assert_eq!(index.seek(10), Some((10, 35)));
+        assert_eq!(index.seek(19), Some((10, 35)));
+        assert_eq!(index.seek(20), Some((20, 72)));
+        assert_eq!(index.seek(u64::MAX), Some((20, 72)));
+        assert_eq!(SparseIndex::default().seek(5), None);
+    }
+
+    #[test]
+    fn probe_tells_compressed_files_apart() {
+        let header = Header {
+            num_symbols: 9,
+            ..Header::default()
+        };
+        let mut file = Vec::new();
+        header.write_to(&mut file).unwrap();
+        file.extend_from_slice(&[0x12, 0x34]);
+
+        assert_eq!(probe(&file), Some(header));
+        for len in 0..file.len() - 2 {
+            assert!(!is_phased_in_file(&file[..len]), "{} bytes", len);
+        }
+
+        let mut state = 0x2545_F491u32;
+        for len in 0..200 {
+            let random: Vec<u8> = (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state as u8
+                })
+                .collect();
+            assert!(!is_phased_in_file(&random));
+
+            // Random bytes after a valid start
+            let mut start = b"PHIN\x01".to_vec();
+            start.extend_from_slice(&random);
+            let _ = probe(&start);
+        }
+    }
+
+    #[test]
+    fn header_fields_are_little_endian() {
+        let header = Header {
+            num_symbols: 0x0403_0201,
+            options: StreamOptions {
+                offset: 0x0807_0605,
+                ..StreamOptions::default()
+            },
+            length_prefix: true,
+            ..Header::default()
+        };
+
+        let mut bytes = Vec::new();
+        header.write_to(&mut bytes).unwrap();
+        assert_eq!(&bytes[5..7], &[0x08, 0x00]);
+        assert_eq!(&bytes[8..12], &[0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(&bytes[13..17], &[0x05, 0x06, 0x07, 0x08]);
+        assert_eq!(read_header(&bytes).unwrap(), header);
+    }
+
+    #[test]
+    fn read_header_rejects_invalid_input() {
+        assert_eq!(read_header(b"PHI"), Err(DecodeError::TruncatedHeader));
+        assert_eq!(read_header(b"ABCD\x01\x00\x00\x00"), Err(DecodeError::InvalidMagic));
+        assert_eq!(read_header(b"PHIN\x09\x00\x00\x00"), Err(DecodeError::UnsupportedVersion(9)));
+        assert_eq!(read_header(b"PHIN\x01\x00\x00\x00\x00\x00\x00\x00\x08"), Err(DecodeError::EmptyAlphabet));
+        assert_eq!(read_header(b"PHIN\x01\x00\x00\x00\x09\x00\x00\x00\x07"), Err(DecodeError::InvalidWordSize(7)));
+        assert_eq!(
+            read_header(b"PHIN\x01\x01\x00\x00\x09\x00\x00\x00\x08\x00\x00\x00\x00\x01\xFF"),
+            Err(DecodeError::TruncatedHeader)
+        );
+    }
+
+    #[test]
+    fn files_without_symbols_are_rejected() {
+        let mut encoder = Encoder::new(PhasedInParams::new(5));
+        encoder.compute_encoded_symbols();
+        let mut file = Vec::new();
+        encoder.encode_bytes(&[0, 1, 2, 3, 4]).write_to(&mut file).unwrap();
+        file[8..12].copy_from_slice(&[0, 0, 0, 0]);
+        assert_eq!(EncodedStream::from_encoded_bytes(&file), Err(DecodeError::EmptyAlphabet));
+        assert_eq!(crate::pipeline::decompress(&file), Err(DecodeError::EmptyAlphabet));
+    }
+}
@@ -0,0 +1,20 @@
+//! Compressor/Decompressor using phased in codes.
+//!
+//! The [`encoder`] and [`decoder`] modules implement the algorithm itself while the [`header`]
+//! module describes the information written in front of every compressed file.
+//! The [`pipeline`] module combines them with the [`transforms`] into compression levels
+//! and the [`stats`] module helps to understand how compressible an input is.
+//! The [`pack`] module compresses many small inputs into a single file that shares one alphabet.
+//! The [`prelude`] re-exports the types that are commonly used together.
+
+pub mod common;
+pub mod decoder;
+pub mod encoder;
+pub mod header;
+pub mod pack;
+pub mod pipeline;
+pub mod prelude;
+pub mod stats;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+pub mod transforms;
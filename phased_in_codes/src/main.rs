@@ -1,34 +1,333 @@
-use crate::common::PhasedInParams;
-use crate::encoder::Encoder;
-use crate::decoder::Decoder;
+use phased_in_codes::common::PhasedInParams;
+use phased_in_codes::header;
+use phased_in_codes::pipeline::{self, CompressionSettings};
+use phased_in_codes::stats::{self, Analysis, Stats};
+use phased_in_codes::transforms::TRANSFORMS;
 use crate::cli::{Cli, Action};
-use std::fs;
+use serde::Serialize;
+use std::{fs, io, path::Path, process, time::{Duration, Instant}};
 
-mod common;
-mod encoder;
-mod decoder;
 mod cli;
 
-fn main() -> std::io::Result<()> {
-    let cli = Cli::from_args().expect("Cli is invalid");
-    let params = PhasedInParams::new(cli.num_symbols);
+/// What running an action produced
+struct Report {
+    input_len: usize,
+    output_len: usize,
+    /// Whether the output file existed before running the action
+    overwrites: bool,
+    /// The statistics of the input, if they were requested
+    stats: Option<Stats>,
+    /// The number of symbols of the phased-in codes
+    num_symbols: u32,
+    /// The transforms that were applied while compressing
+    transforms: Vec<&'static str>,
+}
+
+/// The machine-readable summary of running an action, printed instead of the report with `--json`
+#[derive(Debug, Serialize)]
+struct Summary<'a> {
+    action: &'static str,
+    input_size: usize,
+    output_size: usize,
+    /// The output size divided by the input size
+    ratio: f64,
+    num_symbols: u32,
+    transforms: &'a [&'static str],
+    elapsed_ms: f64,
+}
+
+/// Reads the whole input file, turning the most common failures into friendly messages
+fn read_input(path: &str) -> Result<Vec<u8>, String> {
+    if Path::new(path).is_dir() {
+        return Err(format!("'{}' is a directory, expected a file to read", path));
+    }
+
+    fs::read(path).map_err(|error| match error.kind() {
+        io::ErrorKind::NotFound => format!("'{}' does not exist", path),
+        io::ErrorKind::PermissionDenied => format!("permission denied while reading '{}'", path),
+        _ => format!("could not read '{}': {}", path, error),
+    })
+}
 
-    let input_contents = fs::read(cli.input_file)?;
-    let input_contents = input_contents.as_slice();
+/// Returns a line for every transform with its name, the option that enables it and what it does
+fn list_transforms() -> String {
+    TRANSFORMS
+        .iter()
+        .map(|transform| {
+            let lossless = if transform.lossless { "lossless" } else { "lossy" };
+            format!("{:<8} {:<10} {} ({})\n", transform.name, transform.flag, transform.description, lossless)
+        })
+        .collect()
+}
 
-    match cli.action {
+/// Runs the action of `cli` on `input` in memory and writes the result to the output file,
+/// unless this is a dry run
+fn run(cli: &Cli, input: &[u8]) -> io::Result<Report> {
+    let mut stats = None;
+    let header;
+    let output = match cli.action {
         Action::Compress => {
-            let encoder = Encoder::new(params);
-            let encoded = encoder.encode_bytes(input_contents);
-            encoded.write_to_file(cli.output_file)?;
+            let word_bytes = cli.options.word_size.bytes();
+            if !input.len().is_multiple_of(word_bytes) {
+                let message = format!("the input is not made of whole {}-byte words", word_bytes);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            }
+
+            let params = PhasedInParams::new(cli.num_symbols.expect("Compressing requires the number of symbols"));
+            let settings = CompressionSettings {
+                params,
+                options: cli.options,
+                level: cli.level,
+                store_frequencies: cli.store_freq,
+                padding: cli.padding,
+                densify: cli.densify,
+                alphabet: cli.alphabet.clone(),
+                channels: cli.channels,
+                payload_checksum: cli.payload_checksum,
+                index_interval: None,
+            };
+
+            if cli.stats || cli.dry_run {
+                stats = Some(Stats::from_symbols(&cli.options.symbols_from_bytes(input)));
+            }
+
+            let stream = pipeline::compress(input, &settings)?;
+            header = stream.header().clone();
+            let mut output = Vec::new();
+            stream.write_to(&mut output)?;
+
+            // The explanation assumes the input is encoded as it is, using the parameters of the settings
+            let encoded_as_is =
+                cli.options.word_size.bytes() == 1 && !cli.densify && cli.alphabet.is_none() && cli.channels == 1;
+            if output.len() > input.len() && encoded_as_is {
+                if let Some(explanation) = stats::explain_expansion(input, &settings.params) {
+                    eprintln!("warning: the output is larger than the input: {}", explanation);
+                }
+            }
+
+            output
         }
 
         Action::Decompress => {
-            let decoder = Decoder::new(params);
-            let decoded = decoder.decode_bytes(input_contents);
-            std::fs::write(cli.output_file, decoded.as_slice())?;
+            header = header::read_header(input)?;
+            pipeline::decompress(input)?
         }
+        Action::ListTransforms => unreachable!("Listing the transforms doesn't need an input"),
+        Action::Analyze => unreachable!("Analyzing the input doesn't produce an output"),
+    };
+
+    let overwrites = Path::new(&cli.output_file).exists();
+    if !cli.dry_run {
+        fs::write(&cli.output_file, &output)?;
+    }
+
+    Ok(Report {
+        input_len: input.len(),
+        output_len: output.len(),
+        overwrites,
+        stats,
+        num_symbols: header.num_symbols,
+        transforms: pipeline::applied_transforms(&header),
+    })
+}
+
+/// Returns the machine-readable summary of running `action`, which took `elapsed`
+fn json_summary(action: &Action, report: &Report, elapsed: Duration) -> String {
+    let action = match action {
+        Action::Compress => "compress",
+        Action::Decompress => "decompress",
+        Action::ListTransforms => "list-transforms",
+        Action::Analyze => "analyze",
+    };
+
+    let summary = Summary {
+        action,
+        input_size: report.input_len,
+        output_size: report.output_len,
+        ratio: report.output_len as f64 / report.input_len.max(1) as f64,
+        num_symbols: report.num_symbols,
+        transforms: &report.transforms,
+        elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+    };
+
+    serde_json::to_string(&summary).expect("Serializing the summary can't fail")
+}
+
+/// Returns the report of analyzing `input`, as JSON if `json` is set
+fn analysis_report(input: &[u8], json: bool) -> String {
+    let analysis = Analysis::of_bytes(input);
+    if json {
+        serde_json::to_string(&analysis).expect("Serializing the analysis can't fail")
+    } else {
+        analysis.to_string()
+    }
+}
+
+fn main() {
+    let cli = Cli::from_args().unwrap_or_else(|message| {
+        eprintln!("error: {}", message);
+        process::exit(1);
+    });
+
+    if let Action::ListTransforms = cli.action {
+        print!("{}", list_transforms());
+        return;
+    }
+
+    let input_contents = read_input(&cli.input_file).unwrap_or_else(|message| {
+        eprintln!("error: {}", message);
+        process::exit(1);
+    });
+
+    if let Action::Analyze = cli.action {
+        println!("{}", analysis_report(&input_contents, cli.json));
+        return;
     }
 
-    Ok(())
+    let start = Instant::now();
+    let report = run(&cli, &input_contents).unwrap_or_else(|error| {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    });
+
+    if cli.json {
+        println!("{}", json_summary(&cli.action, &report, start.elapsed()));
+        return;
+    }
+
+    if let Some(stats) = &report.stats {
+        println!("{}", stats);
+    }
+
+    if cli.stats || cli.dry_run {
+        let size = match cli.action {
+            Action::Compress => "compressed size: ",
+            Action::Decompress | Action::ListTransforms | Action::Analyze => "decompressed size:",
+        };
+        println!(
+            "{} {} bytes ({:.2}% of the input)",
+            size,
+            report.output_len,
+            report.output_len as f64 * 100.0 / report.input_len.max(1) as f64
+        );
+    }
+
+    if cli.dry_run {
+        let overwrite = if report.overwrites { ", which already exists" } else { "" };
+        println!("dry run: nothing was written to '{}'{}", cli.output_file, overwrite);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use phased_in_codes::common::{Padding, StreamOptions};
+    use serde_json::json;
+
+    #[test]
+    fn read_input_rejects_directories() {
+        let dir = std::env::temp_dir();
+        let path = dir.to_str().unwrap();
+        let expected = format!("'{}' is a directory, expected a file to read", path);
+        assert_eq!(read_input(path), Err(expected));
+    }
+
+    #[test]
+    fn read_input_rejects_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing_input.bin");
+        let path = path.to_str().unwrap();
+        let expected = format!("'{}' does not exist", path);
+        assert_eq!(read_input(path), Err(expected));
+    }
+
+    #[test]
+    fn list_transforms_includes_every_transform() {
+        let listing = list_transforms();
+        assert_eq!(listing.lines().count(), TRANSFORMS.len());
+        for transform in TRANSFORMS.iter() {
+            assert!(listing.contains(transform.name), "{} is missing", transform.name);
+            assert!(listing.contains(transform.flag), "{} is missing", transform.flag);
+        }
+    }
+
+    #[test]
+    fn dry_run_does_not_write_the_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_file = dir.path().join("output.bin");
+
+        let cli = Cli {
+            num_symbols: Some(4),
+            action: Action::Compress,
+            input_file: String::new(),
+            output_file: output_file.to_str().unwrap().to_owned(),
+            store_freq: false,
+            stats: false,
+            dry_run: true,
+            json: false,
+            densify: false,
+            alphabet: None,
+            channels: 1,
+            payload_checksum: false,
+            options: StreamOptions::default(),
+            level: pipeline::DEFAULT_LEVEL,
+            padding: Padding::default(),
+        };
+
+        let input: Vec<u8> = (0..100).map(|i| i % 4).collect();
+        let report = run(&cli, &input).unwrap();
+        assert!(!output_file.exists());
+        assert!(!report.overwrites);
+        assert_eq!(report.input_len, 100);
+        assert!(report.output_len > 0 && report.output_len < 100);
+        assert_eq!(report.stats.map(|stats| stats.distinct_symbols), Some(4));
+    }
+
+    #[test]
+    fn json_summary_has_the_expected_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_file = dir.path().join("output.bin");
+        let cli = Cli {
+            num_symbols: Some(4),
+            action: Action::Compress,
+            input_file: String::new(),
+            output_file: output_file.to_str().unwrap().to_owned(),
+            store_freq: false,
+            stats: false,
+            dry_run: true,
+            json: true,
+            densify: true,
+            alphabet: None,
+            channels: 1,
+            payload_checksum: false,
+            options: StreamOptions::default(),
+            level: pipeline::DEFAULT_LEVEL,
+            padding: Padding::default(),
+        };
+
+        let input: Vec<u8> = (0..100).map(|i| i % 4).collect();
+        let report = run(&cli, &input).unwrap();
+        let summary = json_summary(&cli.action, &report, Duration::from_millis(5));
+        let summary: serde_json::Value = serde_json::from_str(&summary).unwrap();
+
+        assert_eq!(summary["action"], "compress");
+        assert_eq!(summary["input_size"], 100);
+        assert_eq!(summary["output_size"], report.output_len);
+        assert!(summary["ratio"].as_f64().unwrap() < 1.0);
+        assert_eq!(summary["num_symbols"], 4);
+        assert_eq!(summary["transforms"], json!(["densify"]));
+        assert_eq!(summary["elapsed_ms"], 5.0);
+        assert_eq!(summary.as_object().unwrap().len(), 7);
+    }
+
+    #[test]
+    fn analysis_report_honours_json() {
+        let input: Vec<u8> = (0..100).map(|i| [7, 7, 7, 2][i % 4]).collect();
+        assert!(analysis_report(&input, false).starts_with("symbols:"));
+
+        let report: serde_json::Value = serde_json::from_str(&analysis_report(&input, true)).unwrap();
+        assert_eq!(report["stats"]["distinct_symbols"], 2);
+        assert_eq!(report["max_byte"], 7);
+        assert_eq!(report["densify_helps"], true);
+    }
 }
@@ -0,0 +1,141 @@
+//! Packs many small inputs into a single file, analyzing them together so that they share one alphabet
+//! that is written once instead of once per input.
+//!
+//! A packed file has the following layout:
+//!
+//! Magic:         4 bytes, always [`PACK_MAGIC`](header::PACK_MAGIC)
+//! Header:        The shared [`Header`], whose dense map is the shared alphabet
+//! Files:         The number of packed inputs, as a varint
+//! Payloads:      For every input, the length of its payload in bytes as a varint followed by the payload.
+//!                Every payload starts with a length prefix, so it doesn't depend on the header for its unused bits.
+
+use crate::{
+    common::PhasedInParams,
+    decoder::{
+        DecodeError,
+        Decoder,
+    },
+    encoder::{
+        EncodedStream,
+        Encoder,
+    },
+    header::{
+        self,
+        Header,
+        HeaderReader,
+    },
+    transforms,
+};
+use std::convert::TryFrom;
+
+/// Compresses `inputs` into a single packed file.
+/// All the inputs are scanned first to find the bytes any of them uses, which become the shared alphabet,
+/// and then every input is densified using that alphabet and encoded on its own.
+pub fn pack(inputs: &[&[u8]]) -> Vec<u8> {
+    let mut used = [false; 256];
+    inputs.iter().flat_map(|input| input.iter()).for_each(|byte| used[*byte as usize] = true);
+    let map: Vec<u8> = (0..=u8::MAX).filter(|byte| used[*byte as usize]).collect();
+
+    let params = PhasedInParams::new((map.len() as u32).max(2));
+    let mut encoder = Encoder::new(params.clone()).with_length_prefix(true);
+    encoder.compute_encoded_symbols();
+
+    let header = Header {
+        num_symbols: params.num_symbols,
+        length_prefix: true,
+        dense_map: Some(map.clone()),
+        ..Header::default()
+    };
+
+    let mut bytes = header::PACK_MAGIC.to_vec();
+    header.write_to(&mut bytes).expect("Writing to a Vec can't fail");
+    header::write_varint(&mut bytes, inputs.len() as u64).expect("Writing to a Vec can't fail");
+
+    for input in inputs {
+        let payload = encoder.encode_bytes(&transforms::apply_map(input, &map)).payload_bytes();
+        header::write_varint(&mut bytes, payload.len() as u64).expect("Writing to a Vec can't fail");
+        bytes.extend_from_slice(&payload);
+    }
+
+    bytes
+}
+
+/// Decompresses every input of a file written by [`pack`], in the order they were packed.
+/// Returns [`DecodeError::HeaderMismatch`] if `bytes` don't start with [`PACK_MAGIC`](header::PACK_MAGIC),
+/// or the header lacks the shared alphabet or the length prefixes of a packed file.
+pub fn unpack(bytes: &[u8]) -> Result<Vec<Vec<u8>>, DecodeError> {
+    let mut reader = HeaderReader::new(bytes);
+    if reader.read_bytes(header::PACK_MAGIC.len())? != header::PACK_MAGIC {
+        return Err(DecodeError::HeaderMismatch);
+    }
+
+    let bytes = &bytes[header::PACK_MAGIC.len()..];
+    let (header, header_len) = Header::read(bytes)?;
+    let map = match &header.dense_map {
+        Some(map) if header.length_prefix => map,
+        _ => return Err(DecodeError::HeaderMismatch),
+    };
+
+    let decoder = Decoder::from_header(&header);
+    let mut reader = HeaderReader::new(&bytes[header_len..]);
+    let num_files = reader.read_varint()?;
+
+    let mut files = Vec::new();
+    for _ in 0..num_files {
+        let payload_len = usize::try_from(reader.read_varint()?).map_err(|_| DecodeError::TruncatedHeader)?;
+        let payload = reader.read_bytes(payload_len)?;
+
+        let stream = EncodedStream::from_bytes(payload).with_length_prefix();
+        files.push(transforms::unapply_map(&decoder.decode_stream(&stream)?, map));
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{self, CompressionSettings};
+
+    #[test]
+    fn packed_files_unpack_to_the_inputs() {
+        let inputs: [&[u8]; 3] = [br#"{"id":1,"ok":true}"#, br#"{"id":22,"ok":false}"#, b""];
+        let packed = pack(&inputs);
+
+        let mut alphabet: Vec<u8> = inputs.concat();
+        alphabet.sort_unstable();
+        alphabet.dedup();
+        let (header, _) = Header::read(&packed[header::PACK_MAGIC.len()..]).unwrap();
+        assert_eq!(header.dense_map, Some(alphabet));
+        assert_eq!(unpack(&packed).unwrap(), inputs);
+
+        let settings = CompressionSettings {
+            densify: true,
+            ..CompressionSettings::new(PhasedInParams::new(256))
+        };
+        let separate: usize = inputs
+            .iter()
+            .map(|input| pipeline::compress(input, &settings).unwrap().to_contiguous_file_bytes().len())
+            .sum();
+        assert!(packed.len() < separate, "{} bytes packed, {} bytes separately", packed.len(), separate);
+    }
+
+    #[test]
+    fn unpack_rejects_unpacked_files() {
+        let file = Encoder::new(PhasedInParams::new(4)).encode_bytes(&[0, 1, 2, 3]).to_contiguous_file_bytes();
+        assert_eq!(unpack(&file), Err(DecodeError::HeaderMismatch));
+
+        let packed = pack(&[b"abc", b"cab"]);
+        assert_eq!(unpack(&packed[..packed.len() - 1]), Err(DecodeError::TruncatedHeader));
+        assert_eq!(unpack(&packed[..2]), Err(DecodeError::TruncatedHeader));
+    }
+
+    #[test]
+    fn packs_are_not_mistaken_for_single_streams() {
+        let packed = pack(&[b"abc", b"cab"]);
+        assert!(!header::is_phased_in_file(&packed));
+        assert_eq!(header::probe(&packed), None);
+        assert_eq!(header::read_header(&packed), Err(DecodeError::PackedFile));
+        assert_eq!(pipeline::decompress(&packed), Err(DecodeError::PackedFile));
+    }
+}
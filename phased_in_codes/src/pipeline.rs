@@ -0,0 +1,626 @@
+//! Contains the compression pipeline, which applies the [`transforms`](crate::transforms)
+//! of a compression level around the phased-in [`Encoder`] and [`Decoder`]
+
+use crate::{
+    common::{
+        symbol_frequencies,
+        Padding,
+        PhasedInParams,
+        StreamOptions,
+    },
+    decoder::{
+        DecodeError,
+        Decoder,
+    },
+    encoder::{
+        EncodeError,
+        EncodedStream,
+        Encoder,
+    },
+    header::Header,
+    transforms::{
+        self,
+        Transform,
+    },
+};
+use std::{
+    fs,
+    path::Path,
+};
+
+/// The highest compression level
+pub const MAX_LEVEL: u8 = 9;
+
+/// The compression level used when none is specified
+pub const DEFAULT_LEVEL: u8 = 1;
+
+/// A combination of transforms that is applied around the phased-in codes.
+/// The preset that was used to compress a file is stored in its header.
+///
+/// NOTE: The transforms work on bytes, so the presets that use them encode their output
+/// as 8-bit words with the smallest alphabet that fits, ignoring the parameters and options of the settings.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub enum Preset {
+    /// The input is stored as it is
+    Stored,
+    /// The input is encoded using phased-in codes
+    #[default]
+    Plain,
+    /// Run-length encoding followed by phased-in codes
+    Rle,
+    /// Move-to-front followed by phased-in codes
+    Mtf,
+    /// Run-length encoding, move-to-front and frequency remapping followed by phased-in codes
+    Full,
+}
+
+impl Preset {
+    /// All the presets, from the one used by the lowest level to the one used by the highest level
+    const ALL: [Preset; 5] = [Preset::Stored, Preset::Plain, Preset::Rle, Preset::Mtf, Preset::Full];
+
+    /// Returns the preset of the compression `level`.
+    /// Levels above [`MAX_LEVEL`] are treated as [`MAX_LEVEL`].
+    pub fn for_level(level: u8) -> Self {
+        match level {
+            0 => Preset::Stored,
+            1..=2 => Preset::Plain,
+            3..=4 => Preset::Rle,
+            5..=8 => Preset::Mtf,
+            _ => Preset::Full,
+        }
+    }
+
+    /// Returns the names of the [`TRANSFORMS`](transforms::TRANSFORMS) this preset applies, in the order they are applied
+    pub fn transforms(self) -> &'static [&'static str] {
+        match self {
+            Preset::Stored | Preset::Plain => &[],
+            Preset::Rle => &["rle"],
+            Preset::Mtf => &["mtf"],
+            Preset::Full => &["rle", "mtf", "remap"],
+        }
+    }
+
+    /// Returns the byte that identifies this preset in a header
+    pub fn id(self) -> u8 {
+        Preset::ALL.iter().position(|preset| *preset == self).unwrap() as u8
+    }
+
+    /// Returns the preset identified by `id` in a header, if there is one
+    pub fn from_id(id: u8) -> Option<Self> {
+        Preset::ALL.get(id as usize).copied()
+    }
+}
+
+/// Describes how the input should be compressed
+#[derive(Debug, Clone)]
+pub struct CompressionSettings {
+    pub params: PhasedInParams,
+    pub options: StreamOptions,
+    pub level: u8,
+    pub store_frequencies: bool,
+    pub padding: Padding,
+    /// Whether the input is packed into the smallest alphabet that holds it before compressing it.
+    /// NOTE: Densifying works on bytes, so the parameters and options are replaced by 8-bit words
+    /// with as many symbols as there are distinct bytes.
+    pub densify: bool,
+    /// The bytes the input is made of. If set, the input is densified using this alphabet instead of
+    /// the bytes that appear in it, and any other byte is rejected.
+    pub alphabet: Option<Vec<u8>>,
+    /// The number of channels of channel-interleaved input. If there are more than one, the bytes of
+    /// every channel are grouped together before compressing the input.
+    pub channels: u32,
+    /// Whether the checksum of the encoded bytes is stored in the header
+    pub payload_checksum: bool,
+    /// The number of symbols between the points of the [`SparseIndex`](crate::header::SparseIndex) stored in the header,
+    /// which lets [`decompress_range`] start decoding near the range. Only the [`Plain`](Preset::Plain) preset stores one.
+    pub index_interval: Option<u64>,
+}
+
+impl CompressionSettings {
+    /// Creates settings that compress with `params` at the [`DEFAULT_LEVEL`]
+    pub fn new(params: PhasedInParams) -> Self {
+        Self {
+            params,
+            options: StreamOptions::default(),
+            level: DEFAULT_LEVEL,
+            store_frequencies: false,
+            padding: Padding::default(),
+            densify: false,
+            alphabet: None,
+            channels: 1,
+            payload_checksum: false,
+            index_interval: None,
+        }
+    }
+}
+
+/// Returns the size of `stream` once written to a file
+fn file_len(stream: &EncodedStream) -> usize {
+    stream.to_contiguous_file_bytes().len()
+}
+
+/// Encodes `bytes` as 8-bit words using the smallest alphabet that fits all of them
+fn encode_transformed(bytes: &[u8]) -> EncodedStream {
+    let max_byte = bytes.iter().copied().max().unwrap_or(0) as u32;
+    let mut encoder = Encoder::new(PhasedInParams::new((max_byte + 1).max(2)));
+    encoder.compute_encoded_symbols();
+    encoder.encode_bytes(bytes)
+}
+
+/// Returns the names of the [`TRANSFORMS`](transforms::TRANSFORMS) a file with `header` was compressed with,
+/// in the order they were applied
+/// NOTE: Custom [`Transform`]s are listed as "custom".
+pub fn applied_transforms(header: &Header) -> Vec<&'static str> {
+    let mut applied: Vec<&'static str> = header
+        .transforms
+        .iter()
+        .map(|id| transforms::builtin_transform(*id).map_or("custom", |transform| transform.name()))
+        .collect();
+    if header.channels.is_some() {
+        applied.push("channels");
+    }
+    if header.dense_map.is_some() {
+        applied.push("densify");
+    }
+    applied.extend_from_slice(header.preset.transforms());
+
+    applied
+}
+
+/// Compresses `input` using a single `preset`
+fn compress_with(input: &[u8], settings: &CompressionSettings, preset: Preset) -> EncodedStream {
+    let stream = match preset {
+        Preset::Stored => EncodedStream::from_bytes(input).with_params(&settings.params, settings.options),
+        Preset::Plain => {
+            let mut encoder = Encoder::new(settings.params.clone())
+                .with_options(settings.options)
+                .with_index_interval(settings.index_interval);
+            encoder.compute_encoded_symbols();
+            encoder.encode_bytes(input)
+        }
+        Preset::Rle => {
+            let max_run = input.iter().copied().max().unwrap_or(0).max(1);
+            encode_transformed(&transforms::rle_encode(input, max_run))
+        }
+        Preset::Mtf => encode_transformed(&transforms::mtf_encode(input)),
+        Preset::Full => {
+            let max_run = input.iter().copied().max().unwrap_or(0).max(1);
+            let bytes = transforms::mtf_encode(&transforms::rle_encode(input, max_run));
+            let (bytes, map) = transforms::frequency_remap(&bytes);
+            encode_transformed(&bytes).with_symbol_map(map)
+        }
+    };
+
+    stream.with_preset(preset)
+}
+
+/// Densifies `input` using `alphabet`, which doesn't have to be sorted.
+/// Returns the densified bytes along with the sorted alphabet, which is the map that reverses them.
+fn densify_with_alphabet(input: &[u8], alphabet: &[u8]) -> Result<(Vec<u8>, Vec<u8>), EncodeError> {
+    let mut map = alphabet.to_vec();
+    map.sort_unstable();
+    map.dedup();
+
+    if let Some(byte) = input.iter().find(|byte| map.binary_search(byte).is_err()) {
+        return Err(EncodeError::NotInAlphabet(*byte));
+    }
+
+    Ok((transforms::apply_map(input, &map), map))
+}
+
+/// Compresses `input` according to `settings`.
+///
+/// Every preset up to the one of the requested level is tried and the one that produces the smallest
+/// file wins, so a higher level never produces a larger file than a lower one.
+/// Returns [`EncodeError::NotInAlphabet`] if the input contains a byte outside of the alphabet of the settings,
+/// or [`EncodeError::SymbolOutOfRange`] for a wider word whose symbol doesn't fit in it.
+pub fn compress(input: &[u8], settings: &CompressionSettings) -> Result<EncodedStream, EncodeError> {
+    if settings.channels > 1 {
+        let deinterleaved = transforms::deinterleave(input, settings.channels as usize);
+        let channel_settings = CompressionSettings {
+            channels: 1,
+            ..settings.clone()
+        };
+
+        return Ok(compress(&deinterleaved, &channel_settings)?.with_channels(settings.channels));
+    }
+
+    let densified = match &settings.alphabet {
+        Some(alphabet) => Some(densify_with_alphabet(input, alphabet)?),
+        None if settings.densify => Some(transforms::densify(input)),
+        None => None,
+    };
+
+    if let Some((dense, map)) = densified {
+        let dense_settings = CompressionSettings {
+            params: PhasedInParams::new((map.len() as u32).max(2)),
+            options: StreamOptions::default(),
+            densify: false,
+            alphabet: None,
+            ..settings.clone()
+        };
+
+        return Ok(compress(&dense, &dense_settings)?.with_dense_map(map));
+    }
+
+    Encoder::new(settings.params.clone())
+        .with_options(settings.options)
+        .check_alphabet(input)?;
+
+    let level_preset = Preset::for_level(settings.level);
+    let candidates = Preset::ALL.iter().take(level_preset.id() as usize + 1);
+
+    let stream = candidates
+        .map(|preset| compress_with(input, settings, *preset))
+        .min_by_key(file_len)
+        .unwrap()
+        .with_padding(settings.padding)
+        .with_payload_checksum(settings.payload_checksum);
+
+    if settings.store_frequencies {
+        let symbols = settings.options.symbols_from_bytes(input);
+        Ok(stream.with_frequencies(symbol_frequencies(&symbols, settings.params.num_symbols)))
+    } else {
+        Ok(stream)
+    }
+}
+
+/// Compresses `input` like [`compress`], after applying `transforms` to it in order.
+/// The ids of the transforms are stored in the header, so that [`decompress_with_transforms`] can invert them.
+///
+/// NOTE: The transformed bytes have to fit in the alphabet of the settings.
+pub fn compress_with_transforms(
+    input: &[u8],
+    settings: &CompressionSettings,
+    transforms: &[Box<dyn Transform>],
+) -> Result<EncodedStream, EncodeError> {
+    let transformed = transforms
+        .iter()
+        .fold(input.to_vec(), |bytes, transform| transform.forward(&bytes));
+    let ids = transforms.iter().map(|transform| transform.id()).collect();
+
+    Ok(compress(&transformed, settings)?.with_transforms(ids))
+}
+
+/// Decompresses a file that was compressed using [`compress`], inverting the transforms of its preset.
+/// A compressed file can be compressed again like any other input, in which case it has to be decompressed twice.
+/// Returns [`DecodeError::UnknownTransform`] if the file went through custom transforms,
+/// use [`decompress_with_transforms`] for those.
+pub fn decompress(bytes: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    decompress_with_transforms(bytes, &[])
+}
+
+/// Decompresses a file that was compressed using [`compress_with_transforms`], inverting its transforms in reverse order.
+/// The built-in transforms are always available, while custom ones are looked up by id in `transforms`.
+/// Returns [`DecodeError::UnknownTransform`] if a transform of the file is neither.
+pub fn decompress_with_transforms(bytes: &[u8], transforms: &[Box<dyn Transform>]) -> Result<Vec<u8>, DecodeError> {
+    let stream = EncodedStream::from_encoded_bytes(bytes)?;
+    let header = stream.header();
+
+    let decoded = if header.preset == Preset::Stored {
+        stream.bits().as_slice().to_vec()
+    } else {
+        Decoder::from_header(header).decode_stream(&stream)?
+    };
+
+    let decoded = match header.preset {
+        Preset::Stored | Preset::Plain => decoded,
+        Preset::Rle => transforms::rle_decode(&decoded),
+        Preset::Mtf => transforms::mtf_decode(&decoded),
+        Preset::Full => {
+            // The map is always written with this preset, so a file without it is malformed
+            let map = header.symbol_map.as_deref().ok_or(DecodeError::HeaderMismatch)?;
+            let bytes = transforms::unapply_map(&decoded, map);
+            transforms::rle_decode(&transforms::mtf_decode(&bytes))
+        }
+    };
+
+    let decoded = match &header.dense_map {
+        Some(map) => transforms::unapply_map(&decoded, map),
+        None => decoded,
+    };
+
+    let decoded = match header.channels {
+        Some(channels) => transforms::interleave(&decoded, channels as usize),
+        None => decoded,
+    };
+
+    header.transforms.iter().rev().try_fold(decoded, |bytes, id| {
+        let bytes = match transforms.iter().find(|transform| transform.id() == *id) {
+            Some(transform) => transform.inverse(&bytes),
+            None => transforms::builtin_transform(*id)
+                .ok_or(DecodeError::UnknownTransform(*id))?
+                .inverse(&bytes),
+        };
+        Ok(bytes)
+    })
+}
+
+/// Decompresses the words in `[symbol_start, symbol_end)` of the original input of the compressed file at `path`.
+///
+/// Files compressed with the [`Plain`](Preset::Plain) preset are decoded up to `symbol_end`, starting at the nearest
+/// point of their [`SparseIndex`](crate::header::SparseIndex) before `symbol_start`, see
+/// [`CompressionSettings::index_interval`], or at the start of the payload if they have no index.
+/// The words before `symbol_start` are skipped. The transforms of the other presets
+/// carry state across the whole input, as do densifying and deinterleaving, so those files are
+/// decompressed completely and then sliced.
+/// Returns [`DecodeError::UnexpectedEndOfStream`] if the range goes past the end of the input.
+pub fn decompress_range<P: AsRef<Path>>(path: P, symbol_start: usize, symbol_end: usize) -> Result<Vec<u8>, DecodeError> {
+    let bytes = fs::read(path).map_err(|error| DecodeError::Io(error.kind()))?;
+    let stream = EncodedStream::from_encoded_bytes(&bytes)?;
+    let header = stream.header();
+
+    if header.preset == Preset::Plain
+        && header.dense_map.is_none()
+        && header.channels.is_none()
+        && header.transforms.is_empty()
+    {
+        return Decoder::from_header(header).decode_range(&stream, symbol_start, symbol_end);
+    }
+
+    let word_bytes = header.options.word_size.bytes();
+    let decoded = decompress(&bytes)?;
+    let end = symbol_end.saturating_mul(word_bytes);
+    if end > decoded.len() {
+        return Err(DecodeError::UnexpectedEndOfStream);
+    }
+
+    Ok(decoded[symbol_start.min(symbol_end) * word_bytes..end].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::WordSize;
+
+    #[test]
+    fn every_level_round_trips() {
+        let input: Vec<u8> = (0..200).map(|i| ((i / 7) % 5) as u8).collect();
+        for level in 0..=MAX_LEVEL {
+            let settings = CompressionSettings {
+                level,
+                ..CompressionSettings::new(PhasedInParams::new(5))
+            };
+
+            let mut bytes = Vec::new();
+            compress(&input, &settings).unwrap().write_to(&mut bytes).unwrap();
+            assert_eq!(decompress(&bytes).unwrap(), input, "level {}", level);
+        }
+    }
+
+    #[test]
+    fn full_preset_needs_a_symbol_map() {
+        let input: Vec<u8> = (0..100).map(|i| (i % 7) as u8).collect();
+        let mut encoder = Encoder::new(PhasedInParams::new(7));
+        encoder.compute_encoded_symbols();
+
+        let mut bytes = Vec::new();
+        encoder.encode_bytes(&input).with_preset(Preset::Full).write_to(&mut bytes).unwrap();
+        assert_eq!(decompress(&bytes), Err(DecodeError::HeaderMismatch));
+    }
+
+    #[test]
+    fn compressed_files_round_trip_when_compressed_again() {
+        let input: Vec<u8> = (0..500).map(|i| ((i / 3) % 6) as u8).collect();
+        for level in 0..=MAX_LEVEL {
+            let settings = CompressionSettings {
+                level,
+                payload_checksum: true,
+                ..CompressionSettings::new(PhasedInParams::new(6))
+            };
+            let once = compress(&input, &settings).unwrap().to_contiguous_file_bytes();
+
+            let settings = CompressionSettings {
+                level,
+                ..CompressionSettings::new(PhasedInParams::new(256))
+            };
+            let twice = compress(&once, &settings).unwrap().to_contiguous_file_bytes();
+
+            assert_eq!(decompress(&decompress(&twice).unwrap()).unwrap(), input, "level {}", level);
+        }
+    }
+
+    #[test]
+    fn higher_levels_never_produce_larger_output() {
+        let input: Vec<u8> = (0..1000).map(|i| ((i / 25) % 12) as u8).collect();
+        let sizes: Vec<_> = (0..=MAX_LEVEL)
+            .map(|level| {
+                let settings = CompressionSettings {
+                    level,
+                    ..CompressionSettings::new(PhasedInParams::new(12))
+                };
+                file_len(&compress(&input, &settings).unwrap())
+            })
+            .collect();
+
+        assert!(sizes.windows(2).all(|pair| pair[1] <= pair[0]), "{:?}", sizes);
+        assert!(sizes[MAX_LEVEL as usize] < sizes[1], "{:?}", sizes);
+    }
+
+    #[test]
+    fn decompress_range_matches_a_full_decompress() {
+        let input: Vec<u8> = (0..500u32).map(|i| (i * i % 11) as u8).collect();
+        let dir = tempfile::tempdir().unwrap();
+        for level in [1, MAX_LEVEL].iter() {
+            for index_interval in [None, Some(64)].iter() {
+                let settings = CompressionSettings {
+                    level: *level,
+                    index_interval: *index_interval,
+                    ..CompressionSettings::new(PhasedInParams::new(11))
+                };
+
+                let path = dir.path().join(format!("range_{}.phin", level));
+                compress(&input, &settings).unwrap().write_to_file(&path).unwrap();
+
+                for (start, end) in [(123, 321), (0, 500), (64, 128), (63, 65), (448, 500), (499, 500), (200, 100)].iter() {
+                    let expected = &input[*start.min(end)..*end];
+                    assert_eq!(decompress_range(&path, *start, *end).unwrap(), expected, "level {}, {}..{}", level, start, end);
+                }
+                for (start, end) in [(400, 501), (400, 100_000), (100_000, 100_001)].iter() {
+                    assert_eq!(decompress_range(&path, *start, *end), Err(DecodeError::UnexpectedEndOfStream));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sparse_index_points_at_its_symbols() {
+        let input: Vec<u8> = (0..500u32).map(|i| (i * 7 % 11) as u8).collect();
+        let settings = CompressionSettings {
+            index_interval: Some(64),
+            ..CompressionSettings::new(PhasedInParams::new(11))
+        };
+
+        let mut encoder = Encoder::new(settings.params.clone())
+            .with_length_prefix(true)
+            .with_index_interval(Some(64));
+        encoder.compute_encoded_symbols();
+        let with_length_prefix = encoder.encode_bytes(&input);
+
+        for stream in [compress(&input, &settings).unwrap(), with_length_prefix].iter() {
+            let index = stream.header().index.clone().unwrap();
+            assert_eq!(index.bit_offsets.len(), 7);
+
+            let decoder = Decoder::from_header(stream.header());
+            assert_eq!(decoder.sparse_index(stream, 64), Ok(index));
+        }
+    }
+
+    #[test]
+    fn densified_input_uses_a_dense_alphabet() {
+        let input: Vec<u8> = (0..300).map(|i| [3, 10, 200][i % 3]).collect();
+        let settings = CompressionSettings {
+            densify: true,
+            ..CompressionSettings::new(PhasedInParams::new(201))
+        };
+
+        let stream = compress(&input, &settings).unwrap();
+        assert_eq!(stream.header().num_symbols, 3);
+        assert_eq!(stream.header().dense_map, Some(vec![3, 10, 200]));
+
+        let mut bytes = Vec::new();
+        stream.write_to(&mut bytes).unwrap();
+        assert!(bytes.len() < file_len(&compress(&input, &CompressionSettings::new(PhasedInParams::new(201))).unwrap()));
+        assert_eq!(decompress(&bytes).unwrap(), input);
+    }
+
+    #[test]
+    fn interleaved_channels_round_trip() {
+        // Three channels, each one slowly changing, with a partial last sample
+        let input: Vec<u8> = (0..301).map(|i| [(i / 3) as u8 / 10, 100 + (i / 30) as u8, 200][i % 3]).collect();
+        for level in [1, MAX_LEVEL].iter() {
+            let settings = CompressionSettings {
+                level: *level,
+                channels: 3,
+                ..CompressionSettings::new(PhasedInParams::new(256))
+            };
+
+            let stream = compress(&input, &settings).unwrap();
+            assert_eq!(stream.header().channels, Some(3));
+            assert_eq!(decompress(&stream.to_contiguous_file_bytes()).unwrap(), input, "level {}", level);
+        }
+    }
+
+    #[test]
+    fn explicit_alphabet_is_enforced() {
+        let settings = CompressionSettings {
+            alphabet: Some(vec![67, 65, 66]),
+            ..CompressionSettings::new(PhasedInParams::new(256))
+        };
+
+        let input = b"ABACABA".to_vec();
+        let stream = compress(&input, &settings).unwrap();
+        assert_eq!(stream.header().num_symbols, 3);
+        assert_eq!(stream.header().dense_map, Some(vec![65, 66, 67]));
+
+        let mut bytes = Vec::new();
+        stream.write_to(&mut bytes).unwrap();
+        assert_eq!(decompress(&bytes).unwrap(), input);
+
+        assert_eq!(compress(b"ABAD", &settings).unwrap_err(), EncodeError::NotInAlphabet(b'D'));
+    }
+
+    #[test]
+    fn symbols_outside_of_the_alphabet_are_rejected() {
+        for level in 0..=MAX_LEVEL {
+            for store_frequencies in [false, true] {
+                let settings = CompressionSettings {
+                    level,
+                    store_frequencies,
+                    ..CompressionSettings::new(PhasedInParams::new(4))
+                };
+                assert_eq!(compress(&[0, 1, 9], &settings).unwrap_err(), EncodeError::NotInAlphabet(9), "level {}", level);
+            }
+        }
+
+        let settings = CompressionSettings {
+            options: StreamOptions {
+                word_size: WordSize::Bits16,
+                ..StreamOptions::default()
+            },
+            ..CompressionSettings::new(PhasedInParams::new(300))
+        };
+        assert_eq!(
+            compress(&[0, 1, 44, 1], &settings).unwrap_err(),
+            EncodeError::SymbolOutOfRange { symbol: 300, num_symbols: 300 }
+        );
+    }
+
+    struct NoOp;
+
+    impl Transform for NoOp {
+        fn id(&self) -> u8 {
+            transforms::FIRST_CUSTOM_ID
+        }
+
+        fn forward(&self, bytes: &[u8]) -> Vec<u8> {
+            bytes.to_vec()
+        }
+
+        fn inverse(&self, bytes: &[u8]) -> Vec<u8> {
+            bytes.to_vec()
+        }
+    }
+
+    struct ReverseBytes;
+
+    impl Transform for ReverseBytes {
+        fn id(&self) -> u8 {
+            transforms::FIRST_CUSTOM_ID + 1
+        }
+
+        fn forward(&self, bytes: &[u8]) -> Vec<u8> {
+            bytes.iter().rev().copied().collect()
+        }
+
+        fn inverse(&self, bytes: &[u8]) -> Vec<u8> {
+            self.forward(bytes)
+        }
+    }
+
+    #[test]
+    fn custom_transforms_round_trip() {
+        let input: Vec<u8> = (0..400).map(|i| ((i / 9) % 7) as u8 * 3).collect();
+        let chain: Vec<Box<dyn Transform>> = vec![
+            Box::new(NoOp),
+            Box::new(ReverseBytes),
+            Box::new(transforms::Rle { max_run: 20 }),
+            Box::new(transforms::Mtf),
+        ];
+
+        for level in [1, MAX_LEVEL].iter() {
+            let settings = CompressionSettings {
+                level: *level,
+                ..CompressionSettings::new(PhasedInParams::new(256))
+            };
+
+            let stream = compress_with_transforms(&input, &settings, &chain).unwrap();
+            assert_eq!(stream.header().transforms, vec![128, 129, 1, 0]);
+            assert_eq!(applied_transforms(stream.header())[..4], ["custom", "custom", "rle", "mtf"]);
+
+            let bytes = stream.to_contiguous_file_bytes();
+            assert_eq!(decompress_with_transforms(&bytes, &chain).unwrap(), input, "level {}", level);
+            assert_eq!(decompress(&bytes), Err(DecodeError::UnknownTransform(129)));
+        }
+    }
+}
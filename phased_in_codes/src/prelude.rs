@@ -0,0 +1,40 @@
+//! Re-exports the types that are commonly used together, so that they can be imported at once.
+//!
+//! # Example
+//!
+//! ```
+//! use phased_in_codes::prelude::*;
+//!
+//! let bytes: &[u8] = &[0, 1, 2, 3, 4, 5];
+//! let params = PhasedInParams::new(6);
+//!
+//! let mut encoder = Encoder::new(params.clone());
+//! encoder.compute_encoded_symbols();
+//! let encoded_stream: EncodedStream = encoder.encode_bytes(bytes);
+//!
+//! let decoded: Result<Vec<u8>, DecodeError> = Decoder::new(params).decode_stream(&encoded_stream);
+//! assert_eq!(decoded.unwrap(), bytes);
+//! ```
+
+pub use crate::{
+    common::{
+        BitOrder,
+        Padding,
+        ParamError,
+        PhasedInParams,
+        StreamOptions,
+        WordSize,
+    },
+    decoder::{
+        DecodeError,
+        Decoder,
+    },
+    encoder::{
+        EncodeError,
+        EncodedStream,
+        Encoder,
+        MergeError,
+        PhasedInEncodeExt,
+    },
+    pipeline::CompressionSettings,
+};
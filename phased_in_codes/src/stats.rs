@@ -0,0 +1,346 @@
+//! Contains statistics about an input that help to understand how well it can be compressed
+
+use crate::{
+    common::PhasedInParams,
+    header::Header,
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fmt,
+};
+
+/// Statistics about the symbols of an input
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Stats {
+    pub input_len: usize,
+    pub distinct_symbols: usize,
+    /// The Shannon entropy of the symbols, in bits per symbol
+    pub entropy: f64,
+}
+
+impl Stats {
+    /// Computes the statistics of `symbols`
+    pub fn from_symbols(symbols: &[u32]) -> Self {
+        let mut frequencies: HashMap<u32, usize> = HashMap::new();
+        symbols.iter().for_each(|symbol| *frequencies.entry(*symbol).or_insert(0) += 1);
+
+        let input_len = symbols.len();
+        let entropy = frequencies
+            .values()
+            .map(|frequency| {
+                let probability = *frequency as f64 / input_len as f64;
+                -probability * probability.log2()
+            })
+            .sum();
+
+        Self {
+            input_len,
+            distinct_symbols: frequencies.len(),
+            entropy,
+        }
+    }
+
+    /// Returns whether every symbol of the input is distinct, that is the input is a permutation.
+    /// Such inputs have the highest possible entropy for their length, so phased-in codes can't do much about them.
+    pub fn all_distinct(&self) -> bool {
+        self.input_len > 1 && self.distinct_symbols == self.input_len
+    }
+}
+
+/// A report about an input made of 8-bit words, to help picking the settings it is compressed with
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Analysis {
+    pub stats: Stats,
+    /// The smallest byte of the input, `None` if it's empty
+    pub min_byte: Option<u8>,
+    /// The largest byte of the input, `None` if it's empty
+    pub max_byte: Option<u8>,
+    /// The smallest number of symbols that fits every byte of the input as it is
+    pub recommended_num_symbols: u32,
+    /// Whether densifying the input shortens its codes, because some bytes below the largest one are unused
+    pub densify_helps: bool,
+    /// Whether remapping the bytes by frequency shortens the codes further than densifying them,
+    /// because the most frequent bytes don't already get the short codes
+    pub remap_helps: bool,
+    /// The estimated size of the compressed file in bytes, using the best of the above
+    pub estimated_size: usize,
+}
+
+/// Returns the number of bits it takes to encode an input where `frequencies[rank]` symbols have each rank,
+/// using phased-in codes for `num_symbols` symbols
+fn encoded_bits(frequencies: &[usize], num_symbols: u32) -> usize {
+    let params = PhasedInParams::new(num_symbols);
+    frequencies
+        .iter()
+        .enumerate()
+        .map(|(rank, frequency)| {
+            let long = params.p != 0 && rank as u32 >= params.P;
+            frequency * (params.m as usize + long as usize)
+        })
+        .sum()
+}
+
+impl Analysis {
+    /// Analyzes `input`, estimating the size of every candidate from the lengths of the phased-in codes
+    /// plus the size of the header, including the map of the bytes when one is needed
+    pub fn of_bytes(input: &[u8]) -> Self {
+        let mut frequencies = [0usize; 256];
+        input.iter().for_each(|byte| frequencies[*byte as usize] += 1);
+
+        let stats = Stats::from_symbols(&input.iter().map(|byte| *byte as u32).collect::<Vec<_>>());
+        let min_byte = input.iter().copied().min();
+        let max_byte = input.iter().copied().max();
+        let recommended_num_symbols = (max_byte.unwrap_or(0) as u32 + 1).max(2);
+
+        let mut used: Vec<usize> = frequencies.iter().copied().filter(|frequency| *frequency != 0).collect();
+        let dense_num_symbols = (used.len() as u32).max(2);
+        let as_is_bits = encoded_bits(&frequencies[..recommended_num_symbols as usize], recommended_num_symbols);
+        let dense_bits = encoded_bits(&used, dense_num_symbols);
+        used.sort_unstable_by(|a, b| b.cmp(a));
+        let remapped_bits = encoded_bits(&used, dense_num_symbols);
+
+        let densify_helps = dense_bits < as_is_bits;
+        let remap_helps = remapped_bits < dense_bits;
+        let (num_bits, map) = if remap_helps || densify_helps {
+            (remapped_bits.min(dense_bits), Some(vec![0u8; used.len()]))
+        } else {
+            (as_is_bits, None)
+        };
+
+        let mut header = Vec::new();
+        Header { dense_map: map, ..Header::default() }
+            .write_to(&mut header)
+            .expect("Writing to a Vec can't fail");
+
+        Self {
+            stats,
+            min_byte,
+            max_byte,
+            recommended_num_symbols,
+            densify_helps,
+            remap_helps,
+            estimated_size: header.len() + num_bits.div_ceil(u8::BITS as usize),
+        }
+    }
+}
+
+/// How much the distribution of a window has to differ from the one of the block before it, as their
+/// Jensen-Shannon divergence in bits, for [`suggest_block_boundaries`] to suggest a boundary between them
+const BOUNDARY_DIVERGENCE: f64 = 0.5;
+
+/// Returns the Shannon entropy, in bits per byte, of the bytes counted in `histogram`
+fn histogram_entropy(histogram: &[f64]) -> f64 {
+    let len: f64 = histogram.iter().sum();
+    histogram
+        .iter()
+        .filter(|count| **count != 0.0)
+        .map(|count| {
+            let probability = count / len;
+            -probability * probability.log2()
+        })
+        .sum()
+}
+
+/// Suggests where to split `input` into blocks whose bytes are distributed differently, so that every block
+/// can be compressed with the alphabet that suits it.
+/// The input is scanned `window` bytes at a time and a boundary is suggested at the start of every window whose
+/// bytes are distributed significantly differently from the ones of the block so far. Trailing bytes that
+/// don't fill a window are never split off.
+///
+/// NOTE: A shift in the middle of a window makes the window look like both of its neighbours,
+/// so every block, including the first one, is at least two windows long.
+pub fn suggest_block_boundaries(input: &[u8], window: usize) -> Vec<usize> {
+    if window == 0 {
+        return Vec::new();
+    }
+
+    let mut boundaries = Vec::new();
+    let mut block = [0f64; 256];
+    let mut block_windows = 0usize;
+    for (index, chunk) in input.chunks_exact(window).enumerate() {
+        let mut histogram = [0f64; 256];
+        chunk.iter().for_each(|byte| histogram[*byte as usize] += 1.0);
+
+        if block_windows >= 2 {
+            // The divergence is the entropy of the even mixture minus the mean entropy of the two distributions,
+            // so the block is scaled down to a single window to weigh the same as the window
+            let mixture: Vec<f64> = block
+                .iter()
+                .zip(histogram.iter())
+                .map(|(block, window)| block / block_windows as f64 + window)
+                .collect();
+            let mean_entropy = (histogram_entropy(&block) + histogram_entropy(&histogram)) / 2.0;
+            if histogram_entropy(&mixture) - mean_entropy > BOUNDARY_DIVERGENCE {
+                boundaries.push(index * window);
+                block = [0f64; 256];
+                block_windows = 0;
+            }
+        }
+
+        block.iter_mut().zip(histogram.iter()).for_each(|(block, window)| *block += window);
+        block_windows += 1;
+    }
+
+    boundaries
+}
+
+/// Explains why compressing `input`, made of 8-bit words, with `params` produces more bytes than the input.
+/// Returns `None` if the estimated output, which is the header followed by the phased-in codes, isn't larger.
+pub fn explain_expansion(input: &[u8], params: &PhasedInParams) -> Option<String> {
+    let mut header = Vec::new();
+    Header::default().write_to(&mut header).expect("Writing to a Vec can't fail");
+
+    let num_long_codes = input.iter().filter(|byte| params.rank(**byte as u32) >= params.P).count();
+    let num_bits = input.len() * params.m as usize + if params.p == 0 { 0 } else { num_long_codes };
+    let output_len = header.len() + num_bits.div_ceil(u8::BITS as usize);
+    if output_len <= input.len() {
+        return None;
+    }
+
+    let bits_per_symbol = num_bits as f64 / input.len().max(1) as f64;
+    let explanation = if bits_per_symbol >= u8::BITS as f64 {
+        format!(
+            "an alphabet of {} symbols needs {:.2} bits per symbol, so there are no savings over raw bytes; \
+             consider a smaller alphabet using --densify or a transform using --level",
+            params.num_symbols, bits_per_symbol
+        )
+    } else {
+        format!(
+            "the input is only {} bytes, so the {}-byte header outweighs the {} bytes saved by the codes",
+            input.len(),
+            header.len(),
+            input.len() - (output_len - header.len())
+        )
+    };
+
+    Some(explanation)
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "symbols:          {}", self.input_len)?;
+        writeln!(f, "distinct symbols: {}", self.distinct_symbols)?;
+        write!(f, "entropy:          {:.3} bits per symbol", self.entropy)?;
+
+        if self.all_distinct() {
+            write!(
+                f,
+                "\nincompressible:   every symbol is distinct, so the entropy is as high as it gets \
+                 and there is little to gain by compressing",
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let byte = |byte: Option<u8>| byte.map_or_else(|| String::from("-"), |byte| byte.to_string());
+
+        writeln!(f, "{}", self.stats)?;
+        writeln!(f, "smallest byte:    {}", byte(self.min_byte))?;
+        writeln!(f, "largest byte:     {}", byte(self.max_byte))?;
+        writeln!(f, "num_symbols:      {} (-s {})", self.recommended_num_symbols, self.recommended_num_symbols)?;
+
+        if self.densify_helps {
+            writeln!(
+                f,
+                "densify:          would help, the input only uses {} distinct bytes (--densify)",
+                self.stats.distinct_symbols
+            )?;
+        } else {
+            writeln!(f, "densify:          would not help")?;
+        }
+
+        if self.remap_helps {
+            writeln!(f, "remap:            would help, the most frequent bytes would get the short codes (--level 9)")?;
+        } else {
+            writeln!(f, "remap:            would not help")?;
+        }
+
+        write!(f, "estimated size:   {} bytes", self.estimated_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_of_skewed_input() {
+        let stats = Stats::from_symbols(&[0, 0, 0, 0, 1, 1, 2, 3]);
+        assert_eq!(stats.input_len, 8);
+        assert_eq!(stats.distinct_symbols, 4);
+        assert!((stats.entropy - 1.75).abs() < 1e-9);
+        assert!(!stats.to_string().contains("incompressible"));
+    }
+
+    #[test]
+    fn permutations_are_reported_as_incompressible() {
+        let permutation: Vec<u32> = (0..256).map(|i| (i * 7) % 256).collect();
+        let stats = Stats::from_symbols(&permutation);
+        assert!(stats.all_distinct());
+        assert!((stats.entropy - 8.0).abs() < 1e-9);
+        assert!(stats.to_string().contains("incompressible"));
+    }
+
+    #[test]
+    fn block_boundary_is_suggested_at_a_distribution_shift() {
+        let mut state = 0x2545_F491u32;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        // Small values, then large values, shifting in the middle of a window
+        let input: Vec<u8> = (0..4100).map(|i| if i < 2050 { next() as u8 % 8 } else { 200 + next() as u8 % 50 }).collect();
+        let boundaries = suggest_block_boundaries(&input, 64);
+        assert_eq!(boundaries.len(), 1, "{:?}", boundaries);
+        assert!(boundaries[0].abs_diff(2050) <= 64, "{:?}", boundaries);
+
+        assert!(suggest_block_boundaries(&input[..2000], 64).is_empty());
+        assert!(suggest_block_boundaries(&input, 0).is_empty());
+    }
+
+    #[test]
+    fn random_bytes_are_explained() {
+        let mut state = 0x2545_F491u32;
+        let input: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+
+        let explanation = explain_expansion(&input, &PhasedInParams::new(256)).unwrap();
+        assert!(explanation.contains("256 symbols"), "{}", explanation);
+
+        let skewed = vec![0u8; 4096];
+        assert_eq!(explain_expansion(&skewed, &PhasedInParams::new(4)), None);
+        assert!(explain_expansion(&skewed[..4], &PhasedInParams::new(4)).unwrap().contains("header"));
+    }
+
+    #[test]
+    fn analysis_of_skewed_input_recommends_remapping() {
+        // Every byte up to 5 is used, but the most frequent one gets a long code
+        let mut input = vec![5u8; 90];
+        input.extend(0..5);
+
+        let analysis = Analysis::of_bytes(&input);
+        assert_eq!((analysis.min_byte, analysis.max_byte), (Some(0), Some(5)));
+        assert_eq!(analysis.recommended_num_symbols, 6);
+        assert!(!analysis.densify_helps);
+        assert!(analysis.remap_helps);
+        assert!(analysis.estimated_size < input.len());
+
+        let report = analysis.to_string();
+        assert!(report.contains("remap:            would help"), "{}", report);
+        assert!(report.contains("densify:          would not help"), "{}", report);
+    }
+}
@@ -0,0 +1,44 @@
+//! Helpers for tests that exercise the file format, available with the `test-utils` feature
+
+use crate::{
+    common::PhasedInParams,
+    pipeline::{self, CompressionSettings},
+};
+use std::fs;
+
+/// Compresses `input` with `params`, writes the compressed file to a temporary file, reads it back and
+/// decompresses it. Unlike encoding and decoding in memory, this goes through the header and the file I/O.
+///
+/// NOTE: Panics if any of the steps fails, since it's meant to be used in tests.
+pub fn compress_decompress_via_tempfile(input: &[u8], params: PhasedInParams) -> Vec<u8> {
+    let file = tempfile::NamedTempFile::new().expect("Creating the temporary file failed");
+
+    pipeline::compress(input, &CompressionSettings::new(params))
+        .expect("Compressing the input failed")
+        .write_to_file(file.path())
+        .expect("Writing the temporary file failed");
+
+    let compressed = fs::read(file.path()).expect("Reading the temporary file failed");
+    pipeline::decompress(&compressed).expect("Decompressing the temporary file failed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_via_tempfile_works() {
+        let inputs: Vec<(Vec<u8>, u32)> = vec![
+            (Vec::new(), 2),
+            (vec![0], 2),
+            ((0..9).collect(), 9),
+            ((0..1000).map(|i| (i % 7) as u8).collect(), 7),
+            ((0..=255).collect(), 256),
+        ];
+
+        for (input, num_symbols) in inputs {
+            let output = compress_decompress_via_tempfile(&input, PhasedInParams::new(num_symbols));
+            assert_eq!(output, input, "{} symbols", num_symbols);
+        }
+    }
+}
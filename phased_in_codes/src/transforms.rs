@@ -0,0 +1,345 @@
+//! Contains reversible transforms that can be applied to the input before encoding it,
+//! in order to make it more compressible using phased-in codes.
+//!
+//! Phased-in codes assign the short codewords to the smallest symbols,
+//! so every transform here tries to turn the input into mostly small values.
+
+/// Describes a transform and how it's selected from the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransformInfo {
+    pub name: &'static str,
+    /// The command line option that enables the transform
+    pub flag: &'static str,
+    pub description: &'static str,
+    /// Whether the original input is always restored exactly
+    pub lossless: bool,
+}
+
+/// Every transform that can be applied to the input
+pub const TRANSFORMS: [TransformInfo; 5] = [
+    TransformInfo {
+        name: "channels",
+        flag: "--channels",
+        description: "groups the bytes of every channel of channel-interleaved data together",
+        lossless: true,
+    },
+    TransformInfo {
+        name: "rle",
+        flag: "--level 3",
+        description: "run-length encoding, replaces runs of a byte by a pair of the byte and the run length",
+        lossless: true,
+    },
+    TransformInfo {
+        name: "mtf",
+        flag: "--level 5",
+        description: "move-to-front, turns bytes that repeat often into small values",
+        lossless: true,
+    },
+    TransformInfo {
+        name: "remap",
+        flag: "--level 9",
+        description: "frequency remapping, gives the shortest codes to the most frequent bytes",
+        lossless: true,
+    },
+    TransformInfo {
+        name: "densify",
+        flag: "--densify",
+        description: "packs the bytes the input uses into the smallest alphabet that holds them",
+        lossless: true,
+    },
+];
+
+/// A reversible transform of bytes that can be applied to the input before compressing it, using
+/// [`compress_with_transforms`](crate::pipeline::compress_with_transforms).
+/// Implement it to plug custom transforms into the pipeline.
+pub trait Transform {
+    /// The byte that identifies the transform in the header of a compressed file.
+    /// NOTE: The ids below [`FIRST_CUSTOM_ID`] are reserved for the built-in transforms.
+    fn id(&self) -> u8;
+
+    /// The name of the transform, as listed in [`TRANSFORMS`] for the built-in ones
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+
+    /// Transforms `bytes` before they are compressed
+    fn forward(&self, bytes: &[u8]) -> Vec<u8>;
+
+    /// Reverses [`forward`](Transform::forward) after the bytes are decompressed
+    fn inverse(&self, bytes: &[u8]) -> Vec<u8>;
+}
+
+/// The first id that is free for custom [`Transform`]s
+pub const FIRST_CUSTOM_ID: u8 = 128;
+
+/// The [`mtf_encode`] transform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mtf;
+
+impl Transform for Mtf {
+    fn id(&self) -> u8 {
+        0
+    }
+
+    fn name(&self) -> &'static str {
+        "mtf"
+    }
+
+    fn forward(&self, bytes: &[u8]) -> Vec<u8> {
+        mtf_encode(bytes)
+    }
+
+    fn inverse(&self, bytes: &[u8]) -> Vec<u8> {
+        mtf_decode(bytes)
+    }
+}
+
+/// The [`rle_encode`] transform, with runs of at most `max_run` extra bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rle {
+    pub max_run: u8,
+}
+
+impl Transform for Rle {
+    fn id(&self) -> u8 {
+        1
+    }
+
+    fn name(&self) -> &'static str {
+        "rle"
+    }
+
+    fn forward(&self, bytes: &[u8]) -> Vec<u8> {
+        rle_encode(bytes, self.max_run)
+    }
+
+    fn inverse(&self, bytes: &[u8]) -> Vec<u8> {
+        rle_decode(bytes)
+    }
+}
+
+/// Returns the built-in [`Transform`] identified by `id`, if there is one.
+/// Reversing a transform doesn't depend on its settings, so any settings are fine for the returned transform.
+pub fn builtin_transform(id: u8) -> Option<Box<dyn Transform>> {
+    let builtins: Vec<Box<dyn Transform>> = vec![Box::new(Mtf), Box::new(Rle { max_run: u8::MAX })];
+    builtins.into_iter().find(|transform| transform.id() == id)
+}
+
+/// Move-to-front transform.
+/// Every byte is replaced by its position in a list of all the byte values, and then it is moved
+/// to the front of the list. Bytes that repeat often end up as small values.
+///
+/// NOTE: Since only the bytes that are seen are moved, if all the bytes are less than `N`
+/// then all the output values are less than `N` as well.
+pub fn mtf_encode(bytes: &[u8]) -> Vec<u8> {
+    let mut list: Vec<u8> = (0..=u8::MAX).collect();
+    bytes
+        .iter()
+        .map(|byte| {
+            let position = list.iter().position(|b| b == byte).unwrap();
+            list.remove(position);
+            list.insert(0, *byte);
+            position as u8
+        })
+        .collect()
+}
+
+/// Reverses [`mtf_encode`]
+pub fn mtf_decode(positions: &[u8]) -> Vec<u8> {
+    let mut list: Vec<u8> = (0..=u8::MAX).collect();
+    positions
+        .iter()
+        .map(|position| {
+            let byte = list.remove(*position as usize);
+            list.insert(0, byte);
+            byte
+        })
+        .collect()
+}
+
+/// Run-length encoding.
+/// Whenever a byte appears twice in a row, the pair is followed by the number of extra times
+/// the byte is repeated, which is at most `max_run`. Longer runs simply start a new pair.
+///
+/// NOTE: Capping the counts to `max_run` allows keeping the output inside the alphabet of the input.
+pub fn rle_encode(bytes: &[u8], max_run: u8) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(bytes.len());
+    let mut cursor = 0usize;
+
+    while cursor != bytes.len() {
+        let byte = bytes[cursor];
+        encoded.push(byte);
+        cursor += 1;
+
+        if bytes.get(cursor) == Some(&byte) {
+            encoded.push(byte);
+            cursor += 1;
+
+            let run = bytes[cursor..]
+                .iter()
+                .take(max_run as usize)
+                .take_while(|b| **b == byte)
+                .count();
+            encoded.push(run as u8);
+            cursor += run;
+        }
+    }
+
+    encoded
+}
+
+/// Reverses [`rle_encode`]
+pub fn rle_decode(encoded: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut cursor = 0usize;
+
+    while cursor != encoded.len() {
+        let byte = encoded[cursor];
+        decoded.push(byte);
+        cursor += 1;
+
+        if encoded.get(cursor) == Some(&byte) {
+            decoded.push(byte);
+            let run = encoded.get(cursor + 1).copied().unwrap_or(0);
+            decoded.extend(std::iter::repeat_n(byte, run as usize));
+            cursor = (cursor + 2).min(encoded.len());
+        }
+    }
+
+    decoded
+}
+
+/// Replaces every byte by its rank when the bytes are sorted from the most to the least frequent one,
+/// so that the most frequent bytes receive the short codewords.
+/// Returns the remapped bytes along with the map, where `map[rank]` is the original byte.
+/// Bytes that don't appear in the input aren't part of the map.
+pub fn frequency_remap(bytes: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let mut frequencies = [0usize; 256];
+    bytes.iter().for_each(|byte| frequencies[*byte as usize] += 1);
+
+    let mut map: Vec<u8> = (0..=u8::MAX).filter(|byte| frequencies[*byte as usize] != 0).collect();
+    map.sort_by(|a, b| frequencies[*b as usize].cmp(&frequencies[*a as usize]));
+
+    (apply_map(bytes, &map), map)
+}
+
+/// Packs the bytes that appear in the input into the smallest alphabet that holds them,
+/// replacing every byte by its rank when the used bytes are sorted by value.
+/// Returns the packed bytes along with the map, where `map[rank]` is the original byte.
+/// Use [`unapply_map`] to reverse it.
+///
+/// NOTE: An input that only uses the bytes {3, 10, 200} is packed into the alphabet {0, 1, 2}.
+pub fn densify(bytes: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let mut used = [false; 256];
+    bytes.iter().for_each(|byte| used[*byte as usize] = true);
+
+    let map: Vec<u8> = (0..=u8::MAX).filter(|byte| used[*byte as usize]).collect();
+    (apply_map(bytes, &map), map)
+}
+
+/// Groups the bytes of channel-interleaved `data` by channel, so that all the bytes of the first channel
+/// come first, followed by all the bytes of the second channel and so on.
+/// If the length of `data` isn't a multiple of `channels`, the first channels have one more byte than the rest.
+///
+/// NOTE: Zero channels are treated as a single channel.
+pub fn deinterleave(data: &[u8], channels: usize) -> Vec<u8> {
+    let channels = channels.max(1);
+    (0..channels)
+        .flat_map(|channel| data.iter().skip(channel).step_by(channels).copied())
+        .collect()
+}
+
+/// Reverses [`deinterleave`]
+pub fn interleave(data: &[u8], channels: usize) -> Vec<u8> {
+    let channels = channels.max(1);
+    let mut interleaved = vec![0u8; data.len()];
+    let mut bytes = data.iter();
+
+    for channel in 0..channels {
+        for (byte, source) in interleaved.iter_mut().skip(channel).step_by(channels).zip(&mut bytes) {
+            *byte = *source;
+        }
+    }
+
+    interleaved
+}
+
+/// Replaces every byte with its index in `map`.
+/// Every byte of `bytes` must be present in `map`.
+pub fn apply_map(bytes: &[u8], map: &[u8]) -> Vec<u8> {
+    let mut ranks = [0u8; 256];
+    map.iter().enumerate().for_each(|(rank, byte)| ranks[*byte as usize] = rank as u8);
+    bytes.iter().map(|byte| ranks[*byte as usize]).collect()
+}
+
+/// Reverses [`apply_map`], turning indices of `map` back into the bytes of `map`.
+/// Indices outside of the map are kept as they are.
+pub fn unapply_map(indices: &[u8], map: &[u8]) -> Vec<u8> {
+    indices
+        .iter()
+        .map(|index| map.get(*index as usize).copied().unwrap_or(*index))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mtf_round_trip_works() {
+        let bytes: &[u8] = &[3, 3, 3, 1, 1, 2, 3, 0, 0];
+        let encoded = mtf_encode(bytes);
+        assert_eq!(encoded, vec![3, 0, 0, 2, 0, 3, 2, 3, 0]);
+        assert_eq!(mtf_decode(&encoded), bytes);
+    }
+
+    #[test]
+    fn rle_round_trip_works() {
+        let bytes: &[u8] = &[5, 5, 5, 5, 5, 5, 5, 1, 2, 2, 0];
+        let encoded = rle_encode(bytes, 3);
+        assert_eq!(encoded, vec![5, 5, 3, 5, 5, 0, 1, 2, 2, 0, 0]);
+        assert_eq!(rle_decode(&encoded), bytes);
+    }
+
+    #[test]
+    fn frequency_remap_round_trip_works() {
+        let bytes: &[u8] = &[7, 200, 7, 7, 3, 200];
+        let (remapped, map) = frequency_remap(bytes);
+        assert_eq!(map, vec![7, 200, 3]);
+        assert_eq!(remapped, vec![0, 1, 0, 0, 2, 1]);
+        assert_eq!(unapply_map(&remapped, &map), bytes);
+    }
+
+    #[test]
+    fn interleave_round_trip_works() {
+        let data: &[u8] = &[10, 20, 30, 11, 21, 31, 12, 22, 32, 13];
+        let deinterleaved = deinterleave(data, 3);
+        assert_eq!(deinterleaved, vec![10, 11, 12, 13, 20, 21, 22, 30, 31, 32]);
+        assert_eq!(interleave(&deinterleaved, 3), data);
+
+        for len in 0..data.len() {
+            assert_eq!(interleave(&deinterleave(&data[..len], 3), 3), &data[..len]);
+        }
+    }
+
+    #[test]
+    fn densify_round_trip_works() {
+        let bytes: &[u8] = &[200, 3, 10, 10, 200, 3];
+        let (dense, map) = densify(bytes);
+        assert_eq!(map, vec![3, 10, 200]);
+        assert_eq!(dense, vec![2, 0, 1, 1, 2, 0]);
+        assert_eq!(unapply_map(&dense, &map), bytes);
+    }
+
+    #[test]
+    fn builtin_transforms_are_registered() {
+        for id in 0..FIRST_CUSTOM_ID {
+            if let Some(transform) = builtin_transform(id) {
+                assert_eq!(transform.id(), id);
+                assert!(TRANSFORMS.iter().any(|info| info.name == transform.name()), "{}", transform.name());
+            }
+        }
+        assert_eq!(builtin_transform(Mtf.id()).map(|transform| transform.name()), Some("mtf"));
+        assert_eq!(builtin_transform(FIRST_CUSTOM_ID).map(|transform| transform.name()), None);
+    }
+}